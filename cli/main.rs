@@ -18,7 +18,12 @@ fn main() {
                 .about("Creates a new Zipity project")
                 .arg(Arg::with_name("project_name").required(true))
         )
-        .subcommand(SubCommand::with_name("build"))
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Builds the static site into out/")
+                .arg(Arg::with_name("minify").long("minify").help("Minifies HTML, CSS and JavaScript"))
+        )
+        .subcommand(SubCommand::with_name("check").about("Checks the site for broken links"))
         .subcommand(SubCommand::with_name("serve"))
         .subcommand(
             SubCommand::with_name("add")
@@ -80,13 +85,36 @@ fn main() {
             let mut gitignore_file = File::create(&gitignore_path).expect(
                 "Failed to create .gitignore file"
             );
-            gitignore_file.write_all(b"/target\n/out\n").expect("Failed to write to .gitignore");
+            gitignore_file
+                .write_all(b"/target\n/out\n/.zipity-cache\n")
+                .expect("Failed to write to .gitignore");
+
+            // Create the zipity.toml site configuration file
+            let config_path = Path::new(project_name).join("zipity.toml");
+            let mut config_file = File::create(&config_path).expect(
+                "Failed to create zipity.toml file"
+            );
+            config_file
+                .write_all(
+                    b"base_url = \"http://127.0.0.1:8080\"\n\n[icons]\nsource = \"icon.png\"\n\n[[menus.main]]\nname = \"Home\"\nurl = \"/\"\n"
+                )
+                .expect("Failed to write to zipity.toml");
 
             // Copy the template.html file to the project directory
             let template_path = Path::new("template.html");
             let destination_path = Path::new(project_name).join("template.html");
             fs::copy(template_path, destination_path).expect("Failed to copy template.html");
 
+            // Copy the shortcode templates to the project directory
+            let templates_dir = Path::new("templates");
+            let destination_dir = Path::new(project_name).join("templates");
+            copy_directory(templates_dir, destination_dir).expect("Failed to copy templates");
+
+            // Copy the icon.png favicon source to the project directory
+            let icon_path = Path::new("icon.png");
+            let destination_path = Path::new(project_name).join("icon.png");
+            fs::copy(icon_path, destination_path).expect("Failed to copy icon.png");
+
             // Run 'git init' in the project directory
             Command::new("git")
                 .arg("init")
@@ -136,8 +164,31 @@ This is a placeholder for the Index route.
 
             println!("Created index.md file at {}", index_md_path.to_string_lossy());
         }
-        ("build", Some(_)) => {
+        ("build", Some(build_matches)) => {
             println!("You ran 'build' command");
+
+            let mut args = vec!["run", "--bin", "zipity", "--", "build"];
+            if build_matches.is_present("minify") {
+                args.push("--minify");
+            }
+
+            // The child writes its summary and errors straight to the terminal
+            let status = Command::new("cargo").args(&args).status().expect("Failed to build site");
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+        ("check", Some(_)) => {
+            println!("You ran 'check' command");
+
+            // Exit with the check's status, so broken links fail CI
+            let status = Command::new("cargo")
+                .args(["run", "--bin", "zipity", "--", "check"])
+                .status()
+                .expect("Failed to check site");
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
         }
         ("serve", Some(_)) => {
             println!("You ran 'serve' command");
@@ -147,7 +198,7 @@ This is a placeholder for the Index route.
             println!("Server is running on http://{}:{}", host, port);
 
             let output = Command::new("cargo")
-                .args(&["run", "--bin", "zipity"])
+                .args(["run", "--bin", "zipity"])
                 .output()
                 .expect("Failed to start server");
 
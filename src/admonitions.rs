@@ -0,0 +1,184 @@
+// src/admonitions.rs
+
+use crate::config::Config;
+use crate::render::escape;
+use crate::shortcodes;
+
+// Built-in kinds, GitHub's alert types: name, title and icon
+const DEFAULT_KINDS: [(&str, &str, &str); 5] = [
+    ("note", "Note", "ℹ"),
+    ("tip", "Tip", "💡"),
+    ("important", "Important", "❗"),
+    ("warning", "Warning", "⚠"),
+    ("caution", "Caution", "⛔"),
+];
+
+/// Rewrites admonitions in markdown to `<aside class="admonition <kind>">`
+/// blocks, leaving their bodies as markdown. Both GitHub-style blockquotes and
+/// containers are recognized, with an optional custom title:
+///
+/// ```text
+/// > [!WARNING] Back up first
+/// > This deletes everything.
+///
+/// :::tip
+/// Containers can hold *any* markdown.
+/// :::
+/// ```
+///
+/// Unknown kinds are left as ordinary blockquotes and paragraphs.
+pub fn expand(markdown: &str, config: &Config) -> String {
+    let lines: Vec<&str> = markdown.split_inclusive('\n').collect();
+    let mut output = String::new();
+    let mut fence: Option<&str> = None;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+
+        // Leave fenced code blocks alone
+        match fence {
+            Some(marker) => {
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                }
+                output.push_str(line);
+                i += 1;
+                continue;
+            }
+            None if shortcodes::opening_fence(trimmed).is_some() => {
+                fence = shortcodes::opening_fence(trimmed);
+                output.push_str(line);
+                i += 1;
+                continue;
+            }
+            None => {}
+        }
+
+        if let Some((kind, title)) = blockquote_marker(trimmed).filter(|(kind, _)| is_kind(kind, config)) {
+            let mut body = String::new();
+            i += 1;
+            while i < lines.len() && lines[i].trim_start().starts_with('>') {
+                let quoted = lines[i].trim_start().strip_prefix('>').unwrap_or("");
+                body.push_str(quoted.strip_prefix(' ').unwrap_or(quoted));
+                i += 1;
+            }
+            output.push_str(&admonition(&kind, title, &body, config));
+            continue;
+        }
+
+        if let Some((kind, title)) = container_marker(trimmed).filter(|(kind, _)| is_kind(kind, config)) {
+            if let Some(end) = container_end(&lines, i + 1) {
+                output.push_str(&admonition(&kind, title, &lines[i + 1..end].concat(), config));
+                i = end + 1;
+                continue;
+            }
+        }
+
+        output.push_str(line);
+        i += 1;
+    }
+
+    output
+}
+
+/// `[!NOTE] Optional title` at the start of a blockquote
+fn blockquote_marker(line: &str) -> Option<(String, &str)> {
+    let marker = line.strip_prefix('>')?.trim_start().strip_prefix("[!")?;
+    let (kind, title) = marker.split_once(']')?;
+    Some((kind.to_lowercase(), title.trim()))
+}
+
+/// `:::tip Optional title` opening a container
+fn container_marker(line: &str) -> Option<(String, &str)> {
+    let marker = line.strip_prefix(":::")?.trim_start();
+    let (kind, title) = marker.split_once(char::is_whitespace).unwrap_or((marker, ""));
+    (!kind.is_empty()).then(|| (kind.to_lowercase(), title.trim()))
+}
+
+/// Index of the `:::` line closing the container whose body starts at `start`,
+/// skipping nested containers
+fn container_end(lines: &[&str], start: usize) -> Option<usize> {
+    let mut depth = 1;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        match line.trim() {
+            ":::" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            line if container_marker(line).is_some() => {
+                depth += 1;
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn is_kind(kind: &str, config: &Config) -> bool {
+    config.admonitions.contains_key(kind) || DEFAULT_KINDS.iter().any(|(name, _, _)| *name == kind)
+}
+
+/// The `<aside>` for an admonition. Its body is nested in blank lines so the
+/// markdown parser keeps treating it as markdown.
+fn admonition(kind: &str, title: &str, body: &str, config: &Config) -> String {
+    let default = DEFAULT_KINDS.iter().find(|(name, _, _)| *name == kind);
+    let (default_title, icon) = match config.admonitions.get(kind) {
+        Some(options) => {
+            let default_title = options.title.as_deref().or(default.map(|(_, title, _)| *title));
+            let icon = options.icon.as_deref().or(default.map(|(_, _, icon)| *icon));
+            (default_title.unwrap_or(kind), icon.unwrap_or(""))
+        }
+        None => default.map(|(_, title, icon)| (*title, *icon)).unwrap_or((kind, "")),
+    };
+    let title = if title.is_empty() { default_title } else { title };
+
+    format!(
+        "\n<aside class=\"admonition {}\">\n<p class=\"admonition-title\"><span class=\"admonition-icon\" aria-hidden=\"true\">{}</span>{}</p>\n\n{}\n\n</aside>\n\n",
+        escape(kind),
+        icon,
+        escape(title),
+        expand(body, config).trim_end()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blockquote_admonitions_take_an_optional_title() {
+        let config = Config::default();
+        let html = expand("> [!WARNING] Back up first\n> This deletes *everything*.\n", &config);
+        assert!(html.contains(r#"<aside class="admonition warning">"#));
+        assert!(html.contains(r#"<span class="admonition-icon" aria-hidden="true">⚠</span>Back up first</p>"#));
+        assert!(html.contains("\n\nThis deletes *everything*.\n\n</aside>"));
+
+        let html = expand("> [!NOTE]\n> Body\n", &config);
+        assert!(html.contains("</span>Note</p>"));
+    }
+
+    #[test]
+    fn containers_nest() {
+        let config = Config::default();
+        let html = expand(":::tip\nOuter\n:::caution Careful\nInner\n:::\n:::\nAfter\n", &config);
+        assert!(html.contains(r#"<aside class="admonition tip">"#));
+        assert!(html.contains(r#"<aside class="admonition caution">"#));
+        assert!(html.contains("</span>Careful</p>"));
+        assert!(html.ends_with("</aside>\n\nAfter\n"));
+    }
+
+    #[test]
+    fn unknown_kinds_and_code_are_left_alone() {
+        let mut config = Config::default();
+        let markdown = "> [!UNKNOWN]\n> Quote\n\n```\n:::note\n:::\n```\n";
+        assert_eq!(expand(markdown, &config), markdown);
+
+        config.admonitions.insert("unknown".to_owned(), Default::default());
+        assert!(expand(markdown, &config).contains(r#"<aside class="admonition unknown">"#));
+    }
+}
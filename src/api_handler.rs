@@ -1,6 +1,25 @@
 // src/api_handler.rs
 
 use actix_web::{ web, HttpResponse, Responder };
+use serde::Serialize;
+use crate::config::Config;
+use crate::page::{ self, Page };
+
+/// A page as listed by `/api/pages`
+#[derive(Debug, Serialize)]
+pub struct PageListing {
+    pub url: String,
+    pub lang: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    pub word_count: usize,
+    /// Estimated reading time in minutes
+    pub reading_time: usize,
+    /// HTML excerpt for listings
+    pub summary: String,
+}
 
 pub async fn api_handler(path: web::Path<String>) -> impl Responder {
     let name = &path.into_inner();
@@ -9,6 +28,36 @@ pub async fn api_handler(path: web::Path<String>) -> impl Responder {
     HttpResponse::Ok().json(content)
 }
 
+/// Lists every indexable page with its summary and reading time
+pub async fn pages(listings: web::Data<Vec<PageListing>>) -> HttpResponse {
+    HttpResponse::Ok().json(listings.get_ref())
+}
+
+/// Listings of every page that isn't `noindex`, sorted by URL
+pub fn build_page_listings(config: &Config) -> Result<Vec<PageListing>, Box<dyn std::error::Error>> {
+    page::load_pages(config)?
+        .iter()
+        .filter(|page| !page.front_matter.noindex)
+        .map(|page| page_listing(page, config))
+        .collect()
+}
+
+fn page_listing(page: &Page, config: &Config) -> Result<PageListing, Box<dyn std::error::Error>> {
+    let front_matter = &page.front_matter;
+
+    Ok(PageListing {
+        url: page.url.clone(),
+        lang: page.lang.clone(),
+        title: front_matter.title.clone(),
+        description: front_matter.description.clone(),
+        date: front_matter.date.clone(),
+        tags: front_matter.tags.clone(),
+        word_count: page.word_count,
+        reading_time: page.reading_time,
+        summary: page.summary(config)?,
+    })
+}
+
 fn get_api_content(name: &str) -> serde_json::Value {
     let mut content = serde_json::Map::new();
     content.insert("name".to_owned(), serde_json::Value::String(name.to_owned()));
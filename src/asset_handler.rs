@@ -0,0 +1,322 @@
+// src/asset_handler.rs
+
+use actix_files::NamedFile;
+use actix_web::http::header::{ HeaderValue, CACHE_CONTROL };
+use actix_web::{ web, HttpRequest, HttpResponse };
+use grass::OutputStyle;
+use lightningcss::stylesheet::{ MinifyOptions, ParserOptions, PrinterOptions, StyleSheet };
+use minify_js::{ Session, TopLevelMode };
+use sha2::{ Digest, Sha256 };
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{ Component, Path, PathBuf };
+use crate::config::{ BuildConfig, Config };
+use crate::icons;
+
+const ASSETS_DIR: &str = "static";
+const OUTPUT_DIR: &str = "out/static";
+const MANIFEST_FILE: &str = "out/static/manifest.json";
+
+// Number of hex characters of the content hash added to file names
+const FINGERPRINT_LENGTH: usize = 8;
+
+/// Maps asset paths relative to `static/` (`styles.css`) to their
+/// fingerprinted file names (`styles.3f9a1c2b.css`)
+#[derive(Debug, Default)]
+pub struct AssetManifest {
+    entries: BTreeMap<String, String>,
+    /// Compiled `build.critical_css` stylesheet, inlined with `{{critical_css}}`
+    critical_css: Option<String>,
+}
+
+impl AssetManifest {
+    /// URL of an asset, fingerprinted when the asset is in the manifest
+    pub fn url(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        format!("/static/{}", self.entries.get(path).map(String::as_str).unwrap_or(path))
+    }
+
+    pub fn critical_css(&self) -> Option<&str> {
+        self.critical_css.as_deref()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Writes an asset into `out/static/` under both its own name and a
+    /// content-hashed name, and records it in the manifest
+    pub fn add(&mut self, relative: &Path, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let hash = format!("{:x}", Sha256::digest(contents));
+        let fingerprinted = relative.with_file_name(fingerprinted_name(relative, &hash[..FINGERPRINT_LENGTH]));
+
+        let output_path = Path::new(OUTPUT_DIR).join(relative);
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output_path, contents)?;
+        fs::write(Path::new(OUTPUT_DIR).join(&fingerprinted), contents)?;
+
+        self.entries.insert(url_path(relative), url_path(&fingerprinted));
+        Ok(())
+    }
+
+    fn is_fingerprinted(&self, path: &str) -> bool {
+        self.entries.values().any(|fingerprinted| fingerprinted == path)
+    }
+}
+
+/// Copies `static/` recursively into `out/static/`, writing each file under
+/// both its own name and a content-hashed name, plus a `manifest.json` mapping
+/// one to the other.
+///
+/// Stylesheets have their local `@import`s bundled in, SCSS files are compiled
+/// to CSS (partials starting with `_` are only used through imports, and an
+/// SCSS file can't share its name with a stylesheet), and CSS and JavaScript
+/// are minified when `build.minify` is set. Favicons and the web manifest are
+/// generated from `icons.source`.
+pub fn build_assets(config: &Config) -> Result<AssetManifest, Box<dyn std::error::Error>> {
+    let options = &config.build;
+
+    // Start from an empty directory so stale fingerprinted files don't pile up
+    if Path::new(OUTPUT_DIR).exists() {
+        fs::remove_dir_all(OUTPUT_DIR)?;
+    }
+    fs::create_dir_all(OUTPUT_DIR)?;
+
+    let mut manifest = AssetManifest::default();
+    if Path::new(ASSETS_DIR).is_dir() {
+        copy_assets(Path::new(ASSETS_DIR), options, &mut manifest)?;
+    }
+    icons::build_icons(config, &mut manifest)?;
+
+    fs::write(MANIFEST_FILE, serde_json::to_string_pretty(&manifest.entries)?)?;
+    manifest.critical_css = load_critical_css(options)?;
+    Ok(manifest)
+}
+
+fn copy_assets(
+    dir: &Path,
+    options: &BuildConfig,
+    manifest: &mut AssetManifest
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            copy_assets(&path, options, manifest)?;
+            continue;
+        }
+
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let is_scss = matches!(extension, Some("scss" | "sass"));
+        if is_scss && path.file_name().unwrap_or_default().to_string_lossy().starts_with('_') {
+            continue;
+        }
+
+        let (relative, contents) = match extension {
+            _ if is_scss => {
+                // `styles.scss` and `styles.css` would both be written to `styles.css`
+                for other in ["css", "scss", "sass"].map(|other| path.with_extension(other)) {
+                    if other != path && other.is_file() {
+                        return Err(
+                            format!(
+                                "{} and {} both compile to {}",
+                                path.display(),
+                                other.display(),
+                                path.with_extension("css").display()
+                            ).into()
+                        );
+                    }
+                }
+                let css = grass::from_path(&path, &sass_options(options))?;
+                (path.strip_prefix(ASSETS_DIR)?.with_extension("css"), css.into_bytes())
+            }
+            Some("css") => {
+                let css = bundle_css(&path)?;
+                let css = if options.minify { minify_css(css, &path) } else { css };
+                (path.strip_prefix(ASSETS_DIR)?.to_path_buf(), css.into_bytes())
+            }
+            Some("js") if options.minify => {
+                let source = fs::read(&path)?;
+                let session = Session::new();
+                let mut minified = Vec::new();
+                minify_js::minify(&session, TopLevelMode::Global, &source, &mut minified)
+                    .map_err(|e| format!("Failed to minify {}: {}", path.display(), e))?;
+                (path.strip_prefix(ASSETS_DIR)?.to_path_buf(), minified)
+            }
+            _ => (path.strip_prefix(ASSETS_DIR)?.to_path_buf(), fs::read(&path)?),
+        };
+
+        manifest.add(&relative, &contents)?;
+    }
+
+    Ok(())
+}
+
+fn sass_options(options: &BuildConfig) -> grass::Options<'static> {
+    let style = if options.minify { OutputStyle::Compressed } else { OutputStyle::Expanded };
+    grass::Options::default().style(style).load_path(ASSETS_DIR)
+}
+
+/// Minifies a stylesheet, or leaves it as it is with a warning when it doesn't
+/// parse, since browsers skip what they don't understand
+fn minify_css(css: String, path: &Path) -> String {
+    let minified = StyleSheet::parse(&css, ParserOptions::default())
+        .map_err(|e| e.to_string())
+        .and_then(|mut stylesheet| {
+            stylesheet.minify(MinifyOptions::default()).map_err(|e| e.to_string())?;
+            let printer_options = PrinterOptions { minify: true, ..PrinterOptions::default() };
+            stylesheet.to_css(printer_options).map(|result| result.code).map_err(|e| e.to_string())
+        });
+
+    match minified {
+        Ok(minified) => minified,
+        Err(e) => {
+            eprintln!("Warning: failed to minify {}, copying it as it is: {}", path.display(), e);
+            css
+        }
+    }
+}
+
+/// Reads a stylesheet with its local `@import "file.css";` rules replaced by
+/// the imported files, recursively. Remaining imports are hoisted to the top,
+/// where CSS requires them.
+fn bundle_css(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut imports = String::new();
+    let mut rules = String::new();
+    inline_css_imports(path, &mut Vec::new(), &mut imports, &mut rules)?;
+
+    Ok(imports + &rules)
+}
+
+fn inline_css_imports(
+    path: &Path,
+    importers: &mut Vec<PathBuf>,
+    imports: &mut String,
+    rules: &mut String
+) -> Result<(), Box<dyn std::error::Error>> {
+    let css = fs::read_to_string(path)?;
+
+    importers.push(path.to_path_buf());
+    for line in css.lines() {
+        let output = if line.trim_start().starts_with("@import") { &mut *imports } else { &mut *rules };
+        match css_import(line) {
+            Some(import) => {
+                let import_path = path.parent().unwrap_or(Path::new("")).join(import);
+                if importers.contains(&import_path) {
+                    return Err(
+                        format!("Circular @import of {} in {}", import_path.display(), path.display()).into()
+                    );
+                }
+                inline_css_imports(&import_path, importers, imports, rules)?;
+            }
+            None => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+    importers.pop();
+
+    Ok(())
+}
+
+/// Path of a local, unconditional `@import` rule on its own line
+fn css_import(line: &str) -> Option<&str> {
+    let rule = line.trim().strip_prefix("@import")?.trim().strip_suffix(';')?.trim();
+    let rule = match rule.strip_prefix("url(") {
+        Some(url) => url.strip_suffix(')')?.trim(),
+        None => rule,
+    };
+    let import = rule
+        .strip_prefix('"')
+        .and_then(|rule| rule.strip_suffix('"'))
+        .or_else(|| rule.strip_prefix('\'').and_then(|rule| rule.strip_suffix('\'')))
+        .unwrap_or(rule);
+
+    // Leave remote imports and imports with media queries to the browser
+    let is_remote = import.contains("://") || import.starts_with("//");
+    if is_remote || import.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        return None;
+    }
+    Some(import)
+}
+
+/// Reads the compiled critical stylesheet back from the build output
+fn load_critical_css(options: &BuildConfig) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let critical_css = match &options.critical_css {
+        Some(critical_css) => Path::new(critical_css).with_extension("css"),
+        None => return Ok(None),
+    };
+
+    Ok(Some(fs::read_to_string(Path::new(OUTPUT_DIR).join(critical_css))?))
+}
+
+/// `styles.css` -> `styles.<hash>.css`
+fn fingerprinted_name(path: &Path, hash: &str) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, hash, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, hash),
+    }
+}
+
+fn url_path(path: &Path) -> String {
+    path.iter()
+        .map(|segment| segment.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Loads the manifest written by the last build
+pub fn load_manifest(options: &BuildConfig) -> Result<AssetManifest, Box<dyn std::error::Error>> {
+    let manifest_file = fs::read_to_string(MANIFEST_FILE)?;
+    Ok(AssetManifest {
+        entries: serde_json::from_str(&manifest_file)?,
+        critical_css: load_critical_css(options)?,
+    })
+}
+
+/// Serves fingerprinted assets from the build output with a year-long
+/// immutable cache lifetime, and everything else straight from `static/`
+/// (or from the build output for compiled files such as SCSS stylesheets)
+pub async fn asset(
+    req: HttpRequest,
+    path: web::Path<String>,
+    assets: web::Data<AssetManifest>
+) -> actix_web::Result<HttpResponse> {
+    let path = path.into_inner();
+    if Path::new(&path).components().any(|component| !matches!(component, Component::Normal(_))) {
+        return Ok(HttpResponse::NotFound().body(format!("Asset not found: {}", path)));
+    }
+
+    if assets.is_fingerprinted(&path) {
+        let mut response = NamedFile::open(Path::new(OUTPUT_DIR).join(&path))?.into_response(&req);
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("public, max-age=31536000, immutable"));
+        return Ok(response);
+    }
+
+    let source = Path::new(ASSETS_DIR).join(&path);
+    if source.is_file() {
+        return Ok(NamedFile::open(source)?.into_response(&req));
+    }
+
+    Ok(NamedFile::open(Path::new(OUTPUT_DIR).join(&path))?.into_response(&req))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minify_css_keeps_modern_css() {
+        let css = "@layer base {\n  .card {\n    color: red;\n    & .title { font-weight: bold; }\n  }\n}\n";
+        let minified = minify_css(css.to_owned(), Path::new("a.css"));
+        assert_eq!(minified, "@layer base{.card{color:red;& .title{font-weight:700}}}");
+
+        let invalid = ".card { color: red;\n}}}".to_owned();
+        assert_eq!(minify_css(invalid.clone(), Path::new("a.css")), invalid);
+    }
+}
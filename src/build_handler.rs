@@ -1,87 +1,271 @@
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+use std::collections::{ BTreeMap, HashSet };
 use std::fs;
-use std::path::{ Path };
-use pulldown_cmark::{ html, Options, Parser };
-use serde::Deserialize;
-use serde_yaml;
-
-#[derive(Deserialize)]
-struct FrontMatter {
-    title: String,
-    slug: String,
-    description: Option<String>,
-    keywords: Option<String>,
-    author: Option<String>,
+use std::path::{ Path, PathBuf };
+use crate::asset_handler::{ self, AssetManifest };
+use crate::config::Config;
+use crate::data;
+use crate::images;
+use crate::includes;
+use crate::link_checker::{ self, BrokenLink };
+use crate::page::{ self, Page };
+use crate::render;
+use crate::redirects_handler;
+use crate::robots_handler;
+use crate::search_handler;
+use crate::sitemap_handler;
+
+// Hashes of what each page was last rendered from
+const STAMPS_FILE: &str = ".zipity-cache/build.json";
+
+/// What a build produced, for the summary printed by `build`
+#[derive(Debug, Default)]
+pub struct BuildSummary {
+    pub pages: usize,
+    /// Pages left as they were because nothing they are built from changed
+    pub unchanged: usize,
+    /// Total size of the HTML of every page, unchanged ones included, before
+    /// and after minification
+    pub html_bytes: usize,
+    pub minified_html_bytes: usize,
+    /// Links that don't resolve, when `build.check_links` is set
+    pub broken_links: Vec<BrokenLink>,
 }
 
-pub fn build_static_files() -> Result<(), Box<dyn std::error::Error>> {
+pub fn build_static_files(config: &Config) -> Result<BuildSummary, Box<dyn std::error::Error>> {
+    let mut summary = BuildSummary::default();
+
     // Create the output directory for the static HTML files
     fs::create_dir_all("out")?;
 
-    // Copy static assets to the output directory
-    copy_static_assets()?;
-
-    // Read the Markdown files in the routes directory
-    let entries = fs::read_dir("routes")?;
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension() == Some("md".as_ref()) {
-            let output_dir = Path::new("out");
-            let output_file = output_dir.join(path.file_stem().unwrap()).with_extension("html");
-
-            // Parse Markdown and convert to HTML
-            let markdown_file = fs::read_to_string(&path)?;
-            let split: Vec<&str> = markdown_file.splitn(3, "---").collect();
-            if split.len() == 3 {
-                let front_matter: FrontMatter = serde_yaml::from_str(split[1])?;
-                let mut options = Options::empty();
-                options.insert(Options::ENABLE_STRIKETHROUGH);
-                let parser = Parser::new_ext(split[2], options);
-                let mut html_output = String::new();
-                html::push_html(&mut html_output, parser);
-
-                // Load template
-                let template = fs::read_to_string("out/template.html")?; // Change the path to the template file
-
-                // Replace placeholders in template
-                let html_output = template
-                    .replace("{{title}}", &front_matter.title)
-                    .replace("{{slug}}", &front_matter.slug)
-                    .replace("{{description}}", front_matter.description.as_deref().unwrap_or(""))
-                    .replace("{{keywords}}", front_matter.keywords.as_deref().unwrap_or(""))
-                    .replace("{{author}}", front_matter.author.as_deref().unwrap_or(""))
-                    .replace("{{content}}", &html_output);
+    // Copy static assets to the output directory, with fingerprinted names
+    let assets = asset_handler::build_assets(config)?;
+
+    // Load template
+    let template = fs::read_to_string("template.html")?;
+
+    // Report broken data files even when no template uses them
+    data::load_data()?;
+
+    // Only re-render pages whose inputs changed since the last build, unless
+    // something every page depends on changed
+    let pages = page::load_pages(config)?;
+    let previous: BuildStamps = fs::read_to_string(STAMPS_FILE)
+        .ok()
+        .and_then(|stamps| serde_json::from_str(&stamps).ok())
+        .unwrap_or_default();
+    let mut stamps = BuildStamps {
+        global: global_stamp(config, &assets, &pages)?,
+        pages: BTreeMap::new(),
+        redirects: Vec::new(),
+    };
+
+    // Render the Markdown files in the routes directory
+    for page in &pages {
+        let output_file = output_path(&page.url);
+        if let Some(parent) = output_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let hash = page_stamp(page)?;
+        let unchanged = previous.pages
+            .get(&page.url)
+            .filter(|stamp| previous.global == stamps.global && stamp.hash == hash && output_file.is_file());
+        // The output directory of image variants is emptied with the assets,
+        // so put back the ones an unchanged page uses
+        let unchanged = match unchanged {
+            Some(stamp) if images::restore_variants(&stamp.images)? => Some(stamp),
+            _ => None,
+        };
+        let stamp = match unchanged {
+            Some(stamp) => {
+                summary.unchanged += 1;
+                stamp.clone()
+            }
+            None => {
+                // Convert Markdown to HTML and fill in the template
+                let mut html_output = render::render_page(page, &template, config, &assets, &pages)?;
+                let html_bytes = html_output.len();
+                if config.build.minify_html {
+                    html_output = render::minify_html(&html_output);
+                }
+                let output_bytes = html_output.len();
 
                 // Write the static HTML file
+                let images = images::variant_files(&html_output);
                 fs::write(&output_file, html_output)?;
+                PageStamp { hash, html_bytes, output_bytes, images }
+            }
+        };
+        summary.pages += 1;
+        summary.html_bytes += stamp.html_bytes;
+        summary.minified_html_bytes += stamp.output_bytes;
+        stamps.pages.insert(page.url.clone(), stamp);
+
+        // Copy the files of a page bundle beside the page
+        if let Some(bundle_dir) = page.bundle_dir() {
+            for file in page.bundle_files()? {
+                let output_file = Path::new("out").join(page.url.trim_start_matches('/')).join(&file);
+                if let Some(parent) = output_file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(bundle_dir.join(&file), output_file)?;
             }
         }
     }
 
-    Ok(())
+    // Write the sitemap (and sitemap index, for large sites)
+    let sitemap = sitemap_handler::build_sitemap(config)?;
+    for (file_name, xml) in sitemap.files() {
+        fs::write(Path::new("out").join(file_name), xml)?;
+    }
+
+    // Write robots.txt
+    let robots = robots_handler::build_robots(config)?;
+    fs::write("out/robots.txt", robots.body())?;
+
+    // Write redirects for static hosts, as a `_redirects` file and as pages
+    let redirects = redirects_handler::build_redirects(config)?;
+    fs::write("out/_redirects", redirects.redirects_file())?;
+    for (url, html) in redirects.stubs(config) {
+        let output_file = output_path(url);
+        if let Some(parent) = output_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(output_file, html)?;
+    }
+
+    // Remove the pages and redirect pages of the last build that are gone,
+    // which is all of them after switching `url_style`
+    stamps.redirects = redirects.stubs(config).into_iter().map(|(url, _)| url.to_owned()).collect();
+    let written: HashSet<PathBuf> = stamps.pages.keys().chain(&stamps.redirects).map(|url| output_path(url)).collect();
+    for url in previous.pages.keys().chain(&previous.redirects) {
+        let output_file = output_path(url);
+        if written.contains(&output_file) {
+            continue;
+        }
+        if output_file.is_file() {
+            fs::remove_file(&output_file)?;
+        }
+        // Along with the directory of a pretty URL, when nothing else is in it
+        if let Some(parent) = output_file.parent().filter(|_| url.ends_with('/') && url != "/") {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+
+    if let Some(parent) = Path::new(STAMPS_FILE).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(STAMPS_FILE, serde_json::to_string_pretty(&stamps)?)?;
+
+    // Write the search index for client-side search
+    let search_index = search_handler::build_search_index(config)?;
+    fs::write("out/search-index.json", serde_json::to_string(search_index.documents())?)?;
+
+    // Check links against the finished output tree
+    if config.build.check_links {
+        summary.broken_links = link_checker::check_links(config, &assets)?;
+    }
+
+    Ok(summary)
 }
 
-fn copy_static_assets() -> Result<(), Box<dyn std::error::Error>> {
-    let output_dir = Path::new("out");
-    let assets_dir = Path::new("static");
+/// Output file for a page URL: `/` is written to `out/index.html`,
+/// `/de/about` to `out/de/about.html` and `/de/about/` to
+/// `out/de/about/index.html`
+pub fn output_path(url: &str) -> PathBuf {
+    match url.trim_matches('/') {
+        "" => Path::new("out").join("index.html"),
+        path if url.ends_with('/') => Path::new("out").join(path).join("index.html"),
+        path => Path::new("out").join(format!("{}.html", path)),
+    }
+}
+
+/// Hashes of the inputs of the last build
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BuildStamps {
+    /// Hash of what every page depends on
+    global: String,
+    /// Each page's own inputs and output, by URL
+    pages: BTreeMap<String, PageStamp>,
+    /// URLs of the redirect pages written
+    redirects: Vec<String>,
+}
 
-    if !output_dir.exists() || !output_dir.is_dir() {
-        fs::create_dir_all(&output_dir)?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PageStamp {
+    /// Hash of the page's own inputs
+    hash: String,
+    /// Size of the rendered HTML, and of what was written (after minification),
+    /// so the build summary covers pages that weren't rendered again
+    html_bytes: usize,
+    output_bytes: usize,
+    /// Image variants the page uses, restored from the cache when the page
+    /// isn't rendered again
+    images: Vec<String>,
+}
+
+/// Hash of what every page is rendered from: the configuration, template,
+/// shortcode templates, data files and assets, and the list of pages (for
+/// translation links and navigation)
+fn global_stamp(
+    config: &Config,
+    assets: &AssetManifest,
+    pages: &[Page]
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(format!("{:?}", config));
+    hasher.update(format!("{:?}", assets));
+    hasher.update(fs::read("template.html")?);
+    hash_dir(Path::new("templates"), &mut hasher)?;
+    hash_dir(Path::new(data::DATA_DIR), &mut hasher)?;
+    for page in pages {
+        // Menus and other navigation show the titles of other pages
+        let front_matter = &page.front_matter;
+        hasher.update(page.path.to_string_lossy().as_bytes());
+        hasher.update(format!("{:?}{:?}{:?}", front_matter.title, front_matter.weight, front_matter.menu));
     }
 
-    if assets_dir.exists() && assets_dir.is_dir() {
-        let entries = fs::read_dir(&assets_dir)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash of a page's own inputs: its source, the files it includes, its bundle
+/// and the pages it links to with `@/` links
+fn page_stamp(page: &Page) -> Result<String, Box<dyn std::error::Error>> {
+    let mut hasher = Sha256::new();
+    hasher.update(fs::read(&page.path)?);
+
+    let mut inputs = includes::dependencies(page)?;
+    if let Some(bundle_dir) = page.bundle_dir() {
+        inputs.extend(page.bundle_files()?.iter().map(|file| bundle_dir.join(file)));
+    }
+    inputs.extend(render::internal_link_targets(&includes::expand(page)?));
+    for input in inputs {
+        hasher.update(input.to_string_lossy().as_bytes());
+        // Missing link targets are reported when the page is rendered
+        hasher.update(fs::read(&input).unwrap_or_default());
+    }
 
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-            if path.is_file() {
-                let file_name = path.file_name().unwrap();
-                let output_path = output_dir.join(file_name);
+fn hash_dir(dir: &Path, hasher: &mut Sha256) -> Result<(), Box<dyn std::error::Error>> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
 
-                fs::copy(path, output_path)?;
-            }
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        hasher.update(path.to_string_lossy().as_bytes());
+        if path.is_dir() {
+            hash_dir(&path, hasher)?;
+        } else {
+            hasher.update(fs::read(&path)?);
         }
     }
 
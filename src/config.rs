@@ -0,0 +1,358 @@
+// src/config.rs
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_FILE: &str = "zipity.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Public URL of the site, used for absolute links such as the sitemap
+    pub base_url: String,
+    /// Site name, used in Open Graph tags and JSON-LD
+    pub title: String,
+    pub description: Option<String>,
+    /// Default share image for pages without an `image` in their front matter
+    pub image: Option<String>,
+    /// Twitter handle of the site, e.g. `@zipity`
+    pub twitter: Option<String>,
+    /// Language of pages that aren't in a language directory or suffixed file
+    pub default_language: String,
+    /// Languages the site is published in, keyed by language code (`en`, `de`, ...)
+    pub languages: BTreeMap<String, LanguageConfig>,
+    pub robots: RobotsConfig,
+    pub build: BuildConfig,
+    pub images: ImagesConfig,
+    pub icons: IconsConfig,
+    pub check: CheckConfig,
+    /// Admonition kinds added to (or overriding) the built-in note, tip,
+    /// important, warning and caution
+    pub admonitions: BTreeMap<String, AdmonitionConfig>,
+    pub math: MathConfig,
+    pub highlight: HighlightConfig,
+    pub summary: SummaryConfig,
+    /// Menus for templates (`{{menu.main}}`), keyed by name. Pages can add
+    /// themselves to a menu in their front matter too.
+    pub menus: BTreeMap<String, Vec<MenuItemConfig>>,
+    /// Pages generated from the records of data files
+    pub generate: Vec<GenerateConfig>,
+    /// Redirects from moved pages. Pages can list their old URLs as `aliases`
+    /// in their front matter too.
+    pub redirects: Vec<RedirectConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RedirectConfig {
+    /// Old URL, or a URL prefix ending in `/*` such as `/blog/*`
+    pub from: String,
+    /// New URL, where `:splat` is replaced with what `*` matched
+    pub to: String,
+    /// 301 (the default), 302, 307 or 308
+    pub status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GenerateConfig {
+    /// Data file holding a list of records, relative to the data directory
+    pub data: String,
+    /// Template each record is rendered with, relative to the templates directory
+    pub template: String,
+    /// URL of each page, with `{field}` replaced by the record's fields, such
+    /// as `/products/{slug}`
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MenuItemConfig {
+    pub name: String,
+    pub url: String,
+    /// Items are ordered by weight, lowest first
+    #[serde(default)]
+    pub weight: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SummaryConfig {
+    /// Length of a summary taken from the start of a page without a
+    /// `<!-- more -->` marker
+    pub words: usize,
+    /// Reading speed used for reading times
+    pub words_per_minute: usize,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        SummaryConfig {
+            words: 50,
+            words_per_minute: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MathConfig {
+    /// KaTeX output: `mathml`, `html` (needs the KaTeX stylesheet and fonts)
+    /// or `html_and_mathml`
+    pub output: String,
+    /// TeX macros available to every formula, such as `"\\RR" = "\\mathbb{R}"`
+    pub macros: BTreeMap<String, String>,
+}
+
+impl Default for MathConfig {
+    fn default() -> Self {
+        MathConfig {
+            output: "mathml".to_owned(),
+            macros: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// Highlight fenced code blocks that name their language
+    pub enabled: bool,
+    /// Built-in syntect theme, such as `InspiredGitHub`, `base16-ocean.dark`
+    /// or `Solarized (light)`
+    pub theme: String,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            enabled: true,
+            theme: "InspiredGitHub".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AdmonitionConfig {
+    /// Title shown when the admonition doesn't give one
+    pub title: Option<String>,
+    /// HTML for the icon before the title, such as an emoji or inline SVG
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CheckConfig {
+    /// Report external links that aren't in the allowlist
+    pub external: bool,
+    /// File of known-good external URLs or URL prefixes, one per line
+    pub allowlist: String,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        CheckConfig {
+            external: false,
+            allowlist: ".zipity-cache/external-links.txt".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IconsConfig {
+    /// Square PNG, JPEG or SVG image the favicons are generated from
+    pub source: Option<String>,
+    /// Short site name for the web manifest, defaulting to `title`
+    pub short_name: Option<String>,
+    pub theme_color: String,
+    pub background_color: String,
+}
+
+impl Default for IconsConfig {
+    fn default() -> Self {
+        IconsConfig {
+            source: None,
+            short_name: None,
+            theme_color: "#ffffff".to_owned(),
+            background_color: "#ffffff".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImagesConfig {
+    /// Resize local markdown images into responsive variants
+    pub enabled: bool,
+    /// Widths in pixels to resize to; images are never upscaled
+    pub widths: Vec<u32>,
+    /// Extra formats offered through `<source>` elements (`webp`, `avif`)
+    pub formats: Vec<String>,
+    /// Value of the `sizes` attribute
+    pub sizes: String,
+    /// Width of the variant used as the `<img>` `src`
+    pub default_width: u32,
+    /// Encoding quality for JPEG and AVIF, from 1 to 100
+    pub quality: u8,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        ImagesConfig {
+            enabled: true,
+            widths: vec![480, 960, 1600],
+            formats: vec!["webp".to_owned()],
+            sizes: "(max-width: 960px) 100vw, 960px".to_owned(),
+            default_width: 960,
+            quality: 80,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BuildConfig {
+    /// Minify CSS and JavaScript assets (also enabled by `build --minify`)
+    pub minify: bool,
+    /// Minify rendered HTML pages (also enabled by `build --minify`)
+    pub minify_html: bool,
+    /// Stylesheet in `static/` inlined into the page head with `{{critical_css}}`
+    pub critical_css: Option<String>,
+    /// Check links and images after building (also enabled by `check`)
+    pub check_links: bool,
+    /// Form of page URLs, and where pages are written in `out/`
+    pub url_style: UrlStyle,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlStyle {
+    /// `/about`, written to `out/about.html`
+    #[default]
+    Html,
+    /// `/about/`, written to `out/about/index.html`
+    Pretty,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LanguageConfig {
+    /// Site name in this language, overriding the top-level `title`
+    pub title: Option<String>,
+    /// Translated strings, used in templates as `{{t.key}}`
+    pub strings: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RobotsConfig {
+    /// Serve this file verbatim instead of generating robots.txt
+    pub file: Option<String>,
+    pub rules: Vec<RobotsRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RobotsRule {
+    pub user_agent: String,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub disallow: Vec<String>,
+    pub crawl_delay: Option<u32>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: "http://127.0.0.1:8080".to_owned(),
+            title: "Zipity".to_owned(),
+            description: None,
+            image: None,
+            twitter: None,
+            default_language: "en".to_owned(),
+            languages: BTreeMap::new(),
+            robots: RobotsConfig::default(),
+            build: BuildConfig::default(),
+            images: ImagesConfig::default(),
+            icons: IconsConfig::default(),
+            check: CheckConfig::default(),
+            admonitions: BTreeMap::new(),
+            math: MathConfig::default(),
+            highlight: HighlightConfig::default(),
+            summary: SummaryConfig::default(),
+            menus: BTreeMap::new(),
+            generate: Vec::new(),
+            redirects: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Builds an absolute URL for a site path such as `/about`
+    pub fn absolute_url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path.trim_start_matches('/'))
+    }
+
+    pub fn is_language(&self, code: &str) -> bool {
+        self.languages.contains_key(code)
+    }
+
+    /// Site name for a language, falling back to the top-level `title`
+    pub fn site_title(&self, lang: &str) -> &str {
+        self.languages
+            .get(lang)
+            .and_then(|language| language.title.as_deref())
+            .unwrap_or(&self.title)
+    }
+
+    /// Translated template string, falling back to the default language and
+    /// then to the key itself
+    pub fn translate<'a>(&'a self, lang: &str, key: &'a str) -> &'a str {
+        self.translate_or(lang, key, key)
+    }
+
+    /// Translated string for a key such as `home`, falling back to the default
+    /// language and then to `default`
+    pub fn translate_or<'a>(&'a self, lang: &str, key: &str, default: &'a str) -> &'a str {
+        [lang, self.default_language.as_str()]
+            .iter()
+            .filter_map(|lang| self.languages.get(*lang))
+            .find_map(|language| language.strings.get(key))
+            .map(String::as_str)
+            .unwrap_or(default)
+    }
+}
+
+pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+    if !Path::new(CONFIG_FILE).exists() {
+        return Ok(Config::default());
+    }
+
+    let config_file = fs::read_to_string(CONFIG_FILE)?;
+    let config: Config = toml::from_str(&config_file)?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_falls_back_to_the_default_language() {
+        let config: Config = toml::from_str(
+            r#"
+            [languages.en]
+            strings = { home = "Home", more = "More" }
+
+            [languages.de]
+            strings = { home = "Startseite" }
+            "#
+        ).unwrap();
+        assert_eq!(config.translate_or("de", "home", "Home"), "Startseite");
+        assert_eq!(config.translate_or("de", "more", "more"), "More");
+        assert_eq!(config.translate_or("de", "about", "About"), "About");
+        assert_eq!(config.translate("fr", "missing"), "missing");
+    }
+}
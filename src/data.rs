@@ -0,0 +1,266 @@
+// src/data.rs
+
+use serde_json::{ Map, Value };
+use std::fs;
+use std::path::Path;
+use crate::render::escape;
+
+pub const DATA_DIR: &str = "data";
+
+/// Loads every `.yaml`/`.yml`, `.toml`, `.json` and `.csv` file in the data
+/// directory into one tree, keyed by file name without the extension and by
+/// subdirectory: `data/team.yaml` is `data.team` and `data/releases/v1.toml`
+/// `data.releases.v1`. Each row of a CSV file is an object keyed by the
+/// header row.
+pub fn load_data() -> Result<Value, Box<dyn std::error::Error>> {
+    let dir = Path::new(DATA_DIR);
+    if !dir.is_dir() {
+        return Ok(Value::Object(Map::new()));
+    }
+
+    load_dir(dir)
+}
+
+fn load_dir(dir: &Path) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut tree = Map::new();
+    let mut entries: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let value = if path.is_dir() {
+            load_dir(&path)?
+        } else {
+            match load_file(&path) {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => {
+                    return Err(format!("{}: {}", path.display(), e).into());
+                }
+                None => {
+                    continue;
+                }
+            }
+        };
+
+        if tree.insert(key.to_owned(), value).is_some() {
+            return Err(format!("{}: more than one data file for `{}`", dir.display(), key).into());
+        }
+    }
+
+    Ok(Value::Object(tree))
+}
+
+/// Parses a data file, or `None` for files that aren't data
+pub fn load_file(path: &Path) -> Option<Result<Value, Box<dyn std::error::Error>>> {
+    let parse = |path: &Path| -> Result<Value, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents)?,
+            Some("toml") => toml::from_str(&contents)?,
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => {
+                let mut reader = csv::Reader::from_reader(contents.as_bytes());
+                let headers = reader.headers()?.clone();
+                let mut rows = Vec::new();
+                for record in reader.records() {
+                    let row = headers
+                        .iter()
+                        .zip(record?.iter())
+                        .map(|(header, field)| (header.to_owned(), Value::String(field.to_owned())))
+                        .collect();
+                    rows.push(Value::Object(row));
+                }
+                Value::Array(rows)
+            }
+        })
+    };
+
+    let extension = path.extension().and_then(|extension| extension.to_str())?;
+    matches!(extension, "yaml" | "yml" | "toml" | "json" | "csv").then(|| parse(path))
+}
+
+/// Replaces the data placeholders in a template, read from `source` (for
+/// error messages). `{{data.team.name}}` is replaced with a value from the
+/// data files (escaped), and
+///
+/// ```text
+/// {{#each data.team.members}}<li>{{item.name}}, {{item.role}}</li>{{/each}}
+/// ```
+///
+/// repeats its body for each element of a list, with `item` the element.
+/// List elements can be picked by index (`data.releases.0.version`). Other
+/// placeholders are left for the caller. The data directory is only read when
+/// the template uses it, so it is always up to date.
+pub fn render(template: &str, source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if !template.contains("{{data.") && !template.contains("{{#each data.") {
+        return Ok(template.to_owned());
+    }
+
+    render_with(template, source, &load_data()?, None)
+}
+
+/// Like `render`, with the data already loaded and `item` set outside of
+/// `{{#each}}` blocks too, as for pages generated from records
+pub fn render_with(
+    template: &str,
+    source: &str,
+    data: &Value,
+    item: Option<&Value>
+) -> Result<String, Box<dyn std::error::Error>> {
+    render_scope(template, &Scope { data, item }).map_err(|e| format!("{}: {}", source, e).into())
+}
+
+/// Values a template's placeholders can refer to
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+    data: &'a Value,
+    /// Current element of the innermost `{{#each}}`, or the record of a
+    /// generated page
+    item: Option<&'a Value>,
+}
+
+impl<'a> Scope<'a> {
+    /// Looks up `data.x.y` or `item.x`, or `None` for other placeholders
+    fn resolve(&self, path: &str) -> Option<Result<&'a Value, String>> {
+        let mut segments = path.split('.');
+        let mut value = match (segments.next()?, self.item) {
+            ("data", _) => self.data,
+            ("item", Some(item)) => item,
+            _ => {
+                return None;
+            }
+        };
+
+        for segment in segments {
+            let next = match value {
+                Value::Object(map) => map.get(segment),
+                Value::Array(list) => segment.parse::<usize>().ok().and_then(|i| list.get(i)),
+                _ => None,
+            };
+            value = match next {
+                Some(next) => next,
+                None => {
+                    return Some(Err(format!("no data at `{}`", path)));
+                }
+            };
+        }
+
+        Some(Ok(value))
+    }
+}
+
+fn render_scope(template: &str, scope: &Scope) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let Some(end) = tag.find("}}") else {
+            output.push_str(tag);
+            rest = "";
+            break;
+        };
+        let name = tag[2..end].trim();
+
+        if let Some(path) = name.strip_prefix("#each ").map(str::trim) {
+            let (body, after) = each_body(&tag[end + 2..]).ok_or_else(|| {
+                format!("`{{{{#each {}}}}}` has no `{{{{/each}}}}`", path)
+            })?;
+            let list = match scope.resolve(path) {
+                Some(Ok(Value::Array(list))) => list,
+                Some(Ok(_)) => {
+                    return Err(format!("`{}` is not a list", path));
+                }
+                Some(Err(e)) => {
+                    return Err(e);
+                }
+                None => {
+                    return Err(format!("unknown list `{}` in `{{{{#each}}}}`", path));
+                }
+            };
+            for item in list {
+                output.push_str(&render_scope(body, &Scope { item: Some(item), ..*scope })?);
+            }
+            rest = after;
+            continue;
+        }
+
+        match scope.resolve(name) {
+            Some(value) => output.push_str(&escape(&scalar(value?, name)?)),
+            None => output.push_str(&tag[..end + 2]),
+        }
+        rest = &tag[end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// Splits what follows an `{{#each}}` tag into its body and what comes after
+/// the matching `{{/each}}`
+fn each_body(template: &str) -> Option<(&str, &str)> {
+    let mut depth = 1;
+    let mut search = 0;
+
+    while let Some(i) = template[search..].find("{{").map(|i| search + i) {
+        let end = i + template[i..].find("}}")? + 2;
+        let tag = template[i + 2..end - 2].trim();
+        if tag.starts_with("#each ") {
+            depth += 1;
+        } else if tag == "/each" {
+            depth -= 1;
+            if depth == 0 {
+                return Some((&template[..i], &template[end..]));
+            }
+        }
+        search = end;
+    }
+
+    None
+}
+
+/// Text of a string, number or boolean value (empty for null)
+fn scalar(value: &Value, path: &str) -> Result<String, String> {
+    match value {
+        Value::String(text) => Ok(text.clone()),
+        Value::Null => Ok(String::new()),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        Value::Array(_) => Err(format!("`{}` is a list, use `{{{{#each {}}}}}`", path, path)),
+        Value::Object(_) => Err(format!("`{}` is a table, not a value", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn render_with_fills_in_values_and_lists() {
+        let data = json!({
+            "site": { "name": "Zipity & co" },
+            "releases": [{ "version": "1.0", "notes": ["a", "b"] }, { "version": "0.9", "notes": [] }]
+        });
+        let template = "{{data.site.name}} {{data.releases.0.version}}:\
+            {{#each data.releases}}[{{item.version}}{{#each item.notes}} {{item}}{{/each}}]{{/each}} {{title}}";
+        assert_eq!(
+            render_with(template, "t.html", &data, None).unwrap(),
+            "Zipity &amp; co 1.0:[1.0 a b][0.9] {{title}}"
+        );
+    }
+
+    #[test]
+    fn render_with_reports_bad_placeholders() {
+        let data = json!({ "site": { "name": "Zipity" }, "tags": ["a"] });
+        let error = |template: &str| render_with(template, "t.html", &data, None).unwrap_err().to_string();
+        assert_eq!(error("{{data.site.missing}}"), "t.html: no data at `data.site.missing`");
+        assert_eq!(error("{{data.tags}}"), "t.html: `data.tags` is a list, use `{{#each data.tags}}`");
+        assert_eq!(error("{{#each data.site}}x{{/each}}"), "t.html: `data.site` is not a list");
+        assert_eq!(error("{{#each data.tags}}x"), "t.html: `{{#each data.tags}}` has no `{{/each}}`");
+    }
+}
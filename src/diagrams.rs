@@ -0,0 +1,70 @@
+// src/diagrams.rs
+
+use layout::backends::svg::SVGWriter;
+use layout::gv::{ DotParser, GraphBuilder };
+use sha2::{ Digest, Sha256 };
+use std::fs;
+use std::panic;
+use std::path::Path;
+use crate::page::Page;
+use crate::render::escape;
+
+// Rendered diagrams are kept here between builds, keyed by source hash
+const CACHE_DIR: &str = ".zipity-cache/diagrams";
+
+/// Whether a fenced code block's language is a diagram rendered at build time.
+/// Mermaid has no Rust renderer, so ```` ```mermaid ```` blocks stay code
+/// blocks for a client-side script to pick up.
+pub fn is_diagram(lang: &str) -> bool {
+    matches!(lang, "dot" | "graphviz")
+}
+
+/// Inline SVG for a Graphviz diagram, reusing the SVG cached by an earlier
+/// build when there is one. A diagram that fails to render is shown as its
+/// source in a `<pre>`, with a warning.
+pub fn diagram_html(lang: &str, source: &str, page: &Page) -> String {
+    match cached_svg(source) {
+        Ok(svg) => format!(r#"<figure class="diagram diagram-{}">{}</figure>"#, lang, svg),
+        Err(e) => {
+            eprintln!("Warning: failed to render {} diagram in {}: {}", lang, page.path.display(), e);
+            format!(r#"<pre><code class="language-{}">{}</code></pre>"#, lang, escape(source))
+        }
+    }
+}
+
+fn cached_svg(source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let hash = format!("{:x}", Sha256::digest(source.as_bytes()));
+    let cached = Path::new(CACHE_DIR).join(format!("{}.svg", hash));
+    if let Ok(svg) = fs::read_to_string(&cached) {
+        return Ok(svg);
+    }
+
+    let svg = render_dot(source)?;
+
+    // Write to a temporary file first so an interrupted build can't leave a
+    // truncated diagram in the cache
+    fs::create_dir_all(CACHE_DIR)?;
+    let partial = cached.with_extension("partial");
+    fs::write(&partial, &svg)?;
+    fs::rename(partial, cached)?;
+
+    Ok(svg)
+}
+
+fn render_dot(source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut parser = DotParser::new(source);
+    let graph = parser.process().map_err(|e| format!("invalid DOT ({})", e))?;
+
+    // The layout engine panics on some graphs it can't lay out
+    let svg = panic::catch_unwind(|| {
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual_graph = builder.get();
+        let mut writer = SVGWriter::new();
+        visual_graph.do_it(false, false, false, &mut writer);
+        writer.finalize()
+    }).map_err(|_| "layout failed")?;
+
+    // Drop the XML declaration so the SVG can be inlined in HTML
+    Ok(svg[svg.find("<svg").unwrap_or(0)..].trim().to_owned())
+}
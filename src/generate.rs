@@ -0,0 +1,151 @@
+// src/generate.rs
+
+use serde_json::{ Map, Value };
+use std::fs;
+use std::path::{ Component, Path };
+use crate::config::Config;
+use crate::data;
+use crate::page::{ self, FrontMatter, Page };
+use crate::summary;
+
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// Pages generated from data files by the `[[generate]]` entries of the
+/// config: one page per record in the entry's data file, at the entry's path
+/// with `{field}` replaced by the record's fields.
+///
+/// The page's body is its template rendered like a data template (see
+/// `data::render`), with `{{item.field}}` the record's fields. The record is
+/// its front matter too, so it needs a `title`, and can set a `description`,
+/// `tags` and the like. Its `slug` defaults to the last segment of its URL.
+pub fn generated_pages(config: &Config) -> Result<Vec<Page>, Box<dyn std::error::Error>> {
+    let mut pages = Vec::new();
+    if config.generate.is_empty() {
+        return Ok(pages);
+    }
+
+    let data = data::load_data()?;
+    for generate in &config.generate {
+        let source = Path::new(data::DATA_DIR).join(&generate.data);
+        let error = |message: String| -> Box<dyn std::error::Error> {
+            format!("{}: {}", source.display(), message).into()
+        };
+
+        let records = match data::load_file(&source) {
+            Some(Ok(Value::Array(records))) => records,
+            Some(Ok(_)) => {
+                return Err(error("expected a list of records to generate pages from".to_owned()));
+            }
+            Some(Err(e)) => {
+                return Err(error(e.to_string()));
+            }
+            None => {
+                return Err(error("not a YAML, TOML, JSON or CSV data file".to_owned()));
+            }
+        };
+
+        let template_path = Path::new(TEMPLATES_DIR).join(&generate.template);
+        let template = fs::read_to_string(&template_path).map_err(|e| {
+            format!("{}: {}", template_path.display(), e)
+        })?;
+
+        for (i, record) in records.iter().enumerate() {
+            let Value::Object(fields) = record else {
+                return Err(error(format!("record {} is not a table", i + 1)));
+            };
+            let url = record_url(&generate.path, fields).map_err(|e| error(format!("record {}: {}", i + 1, e)))?;
+            let url = page::canonical_url(&url, config);
+            let key = url.trim_matches('/').to_owned();
+
+            let mut front_matter = fields.clone();
+            front_matter
+                .entry("slug")
+                .or_insert_with(|| Value::String(key.rsplit('/').next().unwrap_or("").to_owned()));
+            let front_matter: FrontMatter = serde_json::from_value(Value::Object(front_matter)).map_err(|e| {
+                error(format!("record {}: {}", i + 1, e))
+            })?;
+
+            let html = data::render_with(&template, &template_path.to_string_lossy(), &data, Some(record))?;
+            let word_count = summary::html_text(&html).split_whitespace().count();
+            pages.push(Page {
+                path: source.clone(),
+                url,
+                lang: config.default_language.clone(),
+                key,
+                front_matter,
+                content: String::new(),
+                content_line: 1,
+                word_count,
+                reading_time: summary::reading_time(word_count, config.summary.words_per_minute),
+                html: Some(html),
+            });
+        }
+    }
+
+    Ok(pages)
+}
+
+/// URL of a generated page: the entry's path with `{field}` replaced by the
+/// record's fields
+fn record_url(path: &str, fields: &Map<String, Value>) -> Result<String, String> {
+    let mut url = String::new();
+    let mut rest = path;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(|| format!("unclosed `{{` in path {}", path))? + start;
+        let name = &rest[start + 1..end];
+        let value = match fields.get(name) {
+            Some(Value::String(value)) => value.clone(),
+            Some(value @ Value::Number(_)) => value.to_string(),
+            Some(_) => {
+                return Err(format!("field `{}` is not a string or number", name));
+            }
+            None => {
+                return Err(format!("no `{}` field for the path {}", name, path));
+            }
+        };
+
+        url.push_str(&rest[..start]);
+        url.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    url.push_str(rest);
+
+    let url = format!("/{}", url.trim_matches('/'));
+    let key = Path::new(&url[1..]);
+    if url == "/" || !key.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(format!("invalid page URL {}", url));
+    }
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(record: Value) -> Map<String, Value> {
+        match record {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn record_url_fills_in_fields() {
+        let record = fields(json!({ "slug": "widget", "year": 2024 }));
+        assert_eq!(record_url("/products/{slug}", &record), Ok("/products/widget".to_owned()));
+        assert_eq!(record_url("{year}/{slug}/", &record), Ok("/2024/widget".to_owned()));
+    }
+
+    #[test]
+    fn record_url_rejects_bad_fields_and_urls() {
+        let record = fields(json!({ "slug": "../etc", "tags": ["a"], "empty": "" }));
+        assert!(record_url("/products/{slug}", &record).unwrap_err().starts_with("invalid page URL"));
+        assert!(record_url("/{tags}", &record).unwrap_err().contains("not a string or number"));
+        assert!(record_url("/{title}", &record).unwrap_err().starts_with("no `title` field"));
+        assert!(record_url("/{empty}", &record).is_err());
+        assert!(record_url("/products/{slug", &record).unwrap_err().starts_with("unclosed"));
+    }
+}
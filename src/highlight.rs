@@ -0,0 +1,73 @@
+// src/highlight.rs
+
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ styled_line_to_highlighted_html, IncludeBackground };
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use crate::config::HighlightConfig;
+use crate::render::escape;
+
+// Loading the bundled syntaxes and themes takes a while, so it is done once
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+/// HTML for a fenced code block highlighted at build time, colored with
+/// inline styles from the configured theme so no stylesheet is needed. The
+/// language is the first word of the fence's info string, a name or file
+/// extension such as `rust` or `rs`.
+///
+/// Returns `None` when highlighting is off or the language is unknown, so the
+/// block is rendered as plain `<pre><code>`. An unknown theme is an error.
+pub fn code_block_html(code: &str, info: &str, options: &HighlightConfig) -> Result<Option<String>, String> {
+    let lang = info.split(|c: char| c.is_whitespace() || c == ',').next().unwrap_or("");
+    if !options.enabled || lang.is_empty() {
+        return Ok(None);
+    }
+
+    let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+    let Some(syntax) = syntaxes.find_syntax_by_token(lang) else {
+        return Ok(None);
+    };
+    let theme = THEMES
+        .get_or_init(ThemeSet::load_defaults)
+        .themes.get(&options.theme)
+        .ok_or_else(|| format!("unknown highlight.theme \"{}\"", options.theme))?;
+
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut html = String::new();
+    for line in LinesWithEndings::from(code) {
+        let regions = highlighter.highlight_line(line, syntaxes).map_err(|e| e.to_string())?;
+        html.push_str(&styled_line_to_highlighted_html(&regions, IncludeBackground::No).map_err(|e| e.to_string())?);
+    }
+
+    let style = match theme.settings.background {
+        Some(color) => format!(r#" style="background-color:#{:02x}{:02x}{:02x};""#, color.r, color.g, color.b),
+        None => String::new(),
+    };
+    Ok(Some(format!(r#"<pre{}><code class="language-{}">{}</code></pre>"#, style, escape(lang), html) + "\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_known_languages_only() {
+        let options = HighlightConfig::default();
+        let html = code_block_html("let x = 1;\n", "rust,ignore", &options).unwrap().unwrap();
+        assert!(html.starts_with(r#"<pre style="background-color:#ffffff;"><code class="language-rust"><span"#));
+        assert!(html.ends_with("</code></pre>\n"));
+        assert_eq!(code_block_html("x\n", "nosuchlang", &options), Ok(None));
+
+        let disabled = HighlightConfig { enabled: false, ..HighlightConfig::default() };
+        assert_eq!(code_block_html("let x = 1;\n", "rust", &disabled), Ok(None));
+    }
+
+    #[test]
+    fn unknown_theme_is_an_error() {
+        let options = HighlightConfig { theme: "Nope".to_owned(), ..HighlightConfig::default() };
+        assert!(code_block_html("let x = 1;\n", "rust", &options).is_err());
+    }
+}
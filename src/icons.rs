@@ -0,0 +1,140 @@
+// src/icons.rs
+
+use image::codecs::ico::{ IcoEncoder, IcoFrame };
+use image::imageops::{ self, FilterType };
+use image::{ DynamicImage, ExtendedColorType, ImageFormat, RgbaImage };
+use serde_json::json;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use crate::asset_handler::AssetManifest;
+use crate::config::Config;
+use crate::render::escape;
+
+// PNG icons generated from the source image, with their size in pixels
+const PNG_ICONS: [(&str, u32); 5] = [
+    ("favicon-16x16.png", 16),
+    ("favicon-32x32.png", 32),
+    ("apple-touch-icon.png", 180),
+    ("android-chrome-192x192.png", 192),
+    ("android-chrome-512x512.png", 512),
+];
+
+// Sizes packed into favicon.ico
+const ICO_SIZES: [u32; 3] = [16, 32, 48];
+
+// Icons listed in site.webmanifest
+const MANIFEST_ICONS: [&str; 2] = ["android-chrome-192x192.png", "android-chrome-512x512.png"];
+
+/// Generates the favicons and `site.webmanifest` from `icons.source` (a PNG,
+/// JPEG or SVG image) into the asset pipeline
+pub fn build_icons(config: &Config, assets: &mut AssetManifest) -> Result<(), Box<dyn std::error::Error>> {
+    let source = match &config.icons.source {
+        Some(source) => Path::new(source),
+        None => {
+            return Ok(());
+        }
+    };
+    let source_image = SourceImage::load(source)?;
+
+    for (file_name, size) in PNG_ICONS {
+        let mut png = Vec::new();
+        source_image.render(size)?.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        assets.add(Path::new(file_name), &png)?;
+    }
+
+    let mut frames = Vec::new();
+    for size in ICO_SIZES {
+        let icon = source_image.render(size)?;
+        frames.push(IcoFrame::as_png(icon.as_raw(), size, size, ExtendedColorType::Rgba8)?);
+    }
+    let mut ico = Vec::new();
+    IcoEncoder::new(&mut ico).encode_images(&frames)?;
+    assets.add(Path::new("favicon.ico"), &ico)?;
+
+    let icons: Vec<_> = MANIFEST_ICONS
+        .iter()
+        .zip([192, 512])
+        .map(|(file_name, size)| {
+            json!({
+                "src": assets.url(file_name),
+                "sizes": format!("{}x{}", size, size),
+                "type": "image/png",
+            })
+        })
+        .collect();
+    let web_manifest =
+        json!({
+            "name": config.title,
+            "short_name": config.icons.short_name.as_deref().unwrap_or(&config.title),
+            "icons": icons,
+            "start_url": "/",
+            "theme_color": config.icons.theme_color,
+            "background_color": config.icons.background_color,
+            "display": "standalone",
+        });
+    assets.add(Path::new("site.webmanifest"), serde_json::to_string_pretty(&web_manifest)?.as_bytes())?;
+
+    Ok(())
+}
+
+/// Head tags for the theme color, favicons and web manifest. Templates include
+/// them with `{{favicons}}`.
+pub fn favicon_tags(config: &Config, assets: &AssetManifest) -> String {
+    let mut tags = vec![
+        format!(r#"<meta name="theme-color" content="{}" />"#, escape(&config.icons.theme_color))
+    ];
+
+    let mut link = |file_name: &str, attributes: &str| {
+        if assets.contains(file_name) {
+            tags.push(format!(r#"<link {} href="{}" />"#, attributes, escape(&assets.url(file_name))));
+        }
+    };
+    link("favicon.ico", r#"rel="icon" sizes="48x48""#);
+    link("apple-touch-icon.png", r#"rel="apple-touch-icon" sizes="180x180""#);
+    link("favicon-32x32.png", r#"rel="icon" type="image/png" sizes="32x32""#);
+    link("favicon-16x16.png", r#"rel="icon" type="image/png" sizes="16x16""#);
+    link("site.webmanifest", r#"rel="manifest""#);
+
+    tags.join("\n    ")
+}
+
+enum SourceImage {
+    Raster(DynamicImage),
+    Svg(Box<resvg::usvg::Tree>),
+}
+
+impl SourceImage {
+    fn load(path: &Path) -> Result<SourceImage, Box<dyn std::error::Error>> {
+        let contents = fs::read(path)?;
+        if path.extension() == Some("svg".as_ref()) {
+            let tree = resvg::usvg::Tree::from_data(&contents, &resvg::usvg::Options::default())?;
+            Ok(SourceImage::Svg(Box::new(tree)))
+        } else {
+            Ok(SourceImage::Raster(image::load_from_memory(&contents)?))
+        }
+    }
+
+    /// Renders the image into a transparent `size`×`size` square, keeping its
+    /// aspect ratio
+    fn render(&self, size: u32) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+        let icon = match self {
+            SourceImage::Raster(image) => image.resize(size, size, FilterType::Lanczos3).to_rgba8(),
+            SourceImage::Svg(tree) => {
+                let svg_size = tree.size();
+                let scale = (size as f32) / svg_size.width().max(svg_size.height());
+                let width = ((svg_size.width() * scale).round() as u32).max(1);
+                let height = ((svg_size.height() * scale).round() as u32).max(1);
+
+                let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height).ok_or("Invalid SVG size")?;
+                resvg::render(tree, resvg::tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+                image::load_from_memory(&pixmap.encode_png()?)?.to_rgba8()
+            }
+        };
+
+        let mut square = RgbaImage::new(size, size);
+        let (width, height) = icon.dimensions();
+        imageops::overlay(&mut square, &icon, ((size - width) / 2).into(), ((size - height) / 2).into());
+        Ok(square)
+    }
+}
@@ -0,0 +1,278 @@
+// src/images.rs
+
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ DynamicImage, GenericImageView, ImageFormat };
+use sha2::{ Digest, Sha256 };
+use std::fs::{ self, File };
+use std::io::{ BufWriter, Write };
+use std::path::{ Component, Path, PathBuf };
+use crate::config::ImagesConfig;
+use crate::render::escape;
+
+// Processed images are kept here between builds, keyed by source hash and size
+const CACHE_DIR: &str = ".zipity-cache/images";
+const OUTPUT_DIR: &str = "out/static/_images";
+const OUTPUT_URL: &str = "/static/_images";
+
+// AVIF encoder speed, from 1 (slowest, smallest) to 10 (fastest)
+const AVIF_SPEED: u8 = 8;
+
+/// A resized copy of an image in one format
+struct Variant {
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+/// HTML for a markdown image. Local raster images become a `<picture>` with
+/// resized WebP/AVIF sources; anything else gets a plain lazy-loaded `<img>`.
+///
+/// `source` is the image file for images that don't live in `static/`, such
+/// as those in page bundles.
+pub fn image_html(
+    src: &str,
+    source: Option<&Path>,
+    alt: &str,
+    title: &str,
+    options: &ImagesConfig
+) -> String {
+    let title = if title.is_empty() {
+        String::new()
+    } else {
+        format!(r#" title="{}""#, escape(title))
+    };
+
+    let source = source.map(Path::to_path_buf).or_else(|| source_path(src));
+    match source.filter(|_| options.enabled) {
+        Some(path) if is_resizable(&path) => {
+            match responsive_image(&path, alt, &title, options) {
+                Ok(html) => {
+                    return html;
+                }
+                Err(e) => eprintln!("Failed to process image {}: {}", path.display(), e),
+            }
+        }
+        _ => {}
+    }
+
+    format!(
+        r#"<img src="{}" alt="{}"{} loading="lazy" decoding="async" />"#,
+        escape(src),
+        escape(alt),
+        title
+    )
+}
+
+/// Source file of a local image: `/static/photo.jpg` and `photo.jpg` both
+/// refer to `static/photo.jpg`
+fn source_path(src: &str) -> Option<PathBuf> {
+    if src.contains("://") || src.starts_with("//") || src.starts_with("data:") {
+        return None;
+    }
+
+    let relative = src.strip_prefix("/static/").unwrap_or(src).trim_start_matches('/');
+    let relative = Path::new(relative);
+    if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+        return None;
+    }
+
+    let path = Path::new("static").join(relative);
+    path.is_file().then_some(path)
+}
+
+fn is_resizable(path: &Path) -> bool {
+    matches!(
+        ImageFormat::from_path(path),
+        Ok(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)
+    )
+}
+
+fn responsive_image(
+    path: &Path,
+    alt: &str,
+    title: &str,
+    options: &ImagesConfig
+) -> Result<String, Box<dyn std::error::Error>> {
+    let contents = fs::read(path)?;
+    let hash = format!("{:x}", Sha256::digest(&contents));
+    let image = image::load_from_memory(&contents)?;
+    let (original_width, _) = image.dimensions();
+
+    // Never upscale: keep the configured widths below the original, plus the original
+    let mut widths: Vec<u32> = options.widths
+        .iter()
+        .copied()
+        .filter(|width| *width < original_width)
+        .collect();
+    widths.push(original_width);
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let fallback_extension = match ImageFormat::from_path(path)? {
+        ImageFormat::Png => "png",
+        ImageFormat::WebP => "webp",
+        _ => "jpg",
+    };
+
+    let mut sources = Vec::new();
+    for format in &options.formats {
+        if format == fallback_extension {
+            continue;
+        }
+        let variants = widths
+            .iter()
+            .map(|width| variant(&image, &stem, &hash[..8], *width, format, options.quality))
+            .collect::<Result<Vec<_>, _>>()?;
+        sources.push(
+            format!(
+                r#"<source type="image/{}" srcset="{}" sizes="{}" />"#,
+                format,
+                srcset(&variants),
+                escape(&options.sizes)
+            )
+        );
+    }
+
+    let fallbacks = widths
+        .iter()
+        .map(|width| variant(&image, &stem, &hash[..8], *width, fallback_extension, options.quality))
+        .collect::<Result<Vec<_>, _>>()?;
+    let default = fallbacks
+        .iter()
+        .find(|fallback| fallback.width >= options.default_width)
+        .unwrap_or(&fallbacks[fallbacks.len() - 1]);
+
+    let img = format!(
+        r#"<img src="{}" srcset="{}" sizes="{}" width="{}" height="{}" alt="{}"{} loading="lazy" decoding="async" />"#,
+        default.url,
+        srcset(&fallbacks),
+        escape(&options.sizes),
+        default.width,
+        default.height,
+        escape(alt),
+        title
+    );
+
+    Ok(format!("<picture>{}{}</picture>", sources.join(""), img))
+}
+
+fn srcset(variants: &[Variant]) -> String {
+    variants
+        .iter()
+        .map(|variant| format!("{} {}w", variant.url, variant.width))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Resizes `image` to `width` in `format`, reusing the cached file from an
+/// earlier build when there is one
+fn variant(
+    image: &DynamicImage,
+    stem: &str,
+    hash: &str,
+    width: u32,
+    format: &str,
+    quality: u8
+) -> Result<Variant, Box<dyn std::error::Error>> {
+    let (original_width, original_height) = image.dimensions();
+    let height = ((original_height as f64) * (width as f64) / (original_width as f64))
+        .round()
+        .max(1.0) as u32;
+
+    let file_name = format!("{}.{}-{}.{}", stem, hash, width, format);
+    let cached = Path::new(CACHE_DIR).join(&file_name);
+    if !cached.exists() {
+        fs::create_dir_all(CACHE_DIR)?;
+        let resized = if width == original_width {
+            image.clone()
+        } else {
+            image.resize_exact(width, height, FilterType::Lanczos3)
+        };
+        encode(&resized, &cached, format, quality)?;
+    }
+
+    let output = Path::new(OUTPUT_DIR).join(&file_name);
+    if !output.exists() {
+        fs::create_dir_all(OUTPUT_DIR)?;
+        fs::copy(&cached, &output)?;
+    }
+
+    Ok(Variant { url: format!("{}/{}", OUTPUT_URL, file_name), width, height })
+}
+
+/// File names of the image variants used in a page's HTML
+pub fn variant_files(html: &str) -> Vec<String> {
+    let mut files: Vec<String> = html
+        .split(&format!("{}/", OUTPUT_URL))
+        .skip(1)
+        .map(|rest| rest.split(['"', '\'', ' ', ',', ')', '<']).next().unwrap_or("").to_owned())
+        .filter(|file| !file.is_empty())
+        .collect();
+    files.sort();
+    files.dedup();
+
+    files
+}
+
+/// Copies image variants from the cache into the build output, which starts
+/// empty on every build, for pages that aren't rendered again. Returns false
+/// when a variant is no longer cached, so the page has to be rendered.
+pub fn restore_variants(files: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    for file in files {
+        let cached = Path::new(CACHE_DIR).join(file);
+        if !cached.is_file() {
+            return Ok(false);
+        }
+        let output = Path::new(OUTPUT_DIR).join(file);
+        if !output.exists() {
+            fs::create_dir_all(OUTPUT_DIR)?;
+            fs::copy(&cached, &output)?;
+        }
+    }
+
+    Ok(true)
+}
+
+fn encode(
+    image: &DynamicImage,
+    path: &Path,
+    format: &str,
+    quality: u8
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Write to a temporary file first so an interrupted build can't leave a
+    // truncated image in the cache
+    let partial = path.with_extension("partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+
+    match format {
+        "jpg" => {
+            let encoder = JpegEncoder::new_with_quality(&mut writer, quality);
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        "png" => image.write_to(&mut writer, ImageFormat::Png)?,
+        "webp" => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut writer, ImageFormat::WebP)?,
+        "avif" => {
+            let encoder = AvifEncoder::new_with_speed_quality(&mut writer, AVIF_SPEED, quality);
+            DynamicImage::ImageRgba8(image.to_rgba8()).write_with_encoder(encoder)?;
+        }
+        _ => {
+            return Err(format!("Unsupported image format: {}", format).into());
+        }
+    }
+
+    writer.flush()?;
+    fs::rename(partial, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variant_files_reads_srcsets_and_sources() {
+        let html = r#"<picture><source type="image/webp" srcset="/static/_images/a.1234-480.webp 480w, /static/_images/a.1234-960.webp 960w" /><img src="/static/_images/a.1234-960.jpg" srcset="/static/_images/a.1234-960.jpg 960w" /></picture><img src="/static/b.png" />"#;
+        assert_eq!(variant_files(html), vec!["a.1234-480.webp", "a.1234-960.jpg", "a.1234-960.webp"]);
+    }
+}
@@ -0,0 +1,365 @@
+// src/includes.rs
+
+use std::fs;
+use std::ops::Range;
+use std::path::{ Component, Path, PathBuf };
+use crate::page::Page;
+use crate::shortcodes;
+
+/// The page's markdown body with its `{{ include "..." }}` directives replaced
+/// by the files they name, recursively.
+///
+/// Paths are relative to the project directory. An include can be limited to
+/// a range of lines (`lines="3-10"`, `lines="3-"`) or to a region marked in the
+/// included file with `<!-- region linux -->` and `<!-- endregion -->` lines
+/// (`region="linux"`). Includes in code blocks and code spans are left as
+/// they are.
+///
+/// `{{ code "examples/server.rs" lines="10-40" lang="rust" }}` embeds a source
+/// file as a fenced code block instead, so examples can be the real code. It
+/// takes the same `lines` and `region` options, with region markers written as
+/// comments in the file's language (`// region setup`, `# endregion`), and
+/// the language defaults to the file's extension. The embedded lines are
+/// dedented, and highlighted like any fenced block when the page is rendered.
+pub fn expand(page: &Page) -> Result<String, Box<dyn std::error::Error>> {
+    expand_with_sources(page).map(|(markdown, _)| markdown)
+}
+
+/// Like `expand`, with where each part of the expanded markdown was written
+pub fn expand_with_sources(page: &Page) -> Result<(String, SourceMap), Box<dyn std::error::Error>> {
+    let mut stack = vec![page.path.clone()];
+    splice(&page.content, page.content_line, &mut stack, &mut Vec::new())
+}
+
+/// Every file a page includes or embeds, directly or through other includes
+pub fn dependencies(page: &Page) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut stack = vec![page.path.clone()];
+    let mut dependencies = Vec::new();
+    splice(&page.content, page.content_line, &mut stack, &mut dependencies)?;
+
+    Ok(dependencies)
+}
+
+/// Where each part of a piece of markdown was written, so errors found after
+/// includes are spliced in (or math and shortcodes are swapped for
+/// placeholders) point at the right file and line
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    /// Offset in the markdown where each run of text from one file starts,
+    /// with the file and the line it starts on
+    runs: Vec<(usize, PathBuf, usize)>,
+}
+
+impl SourceMap {
+    /// Map of markdown written in `file`, starting at `line`
+    pub fn new(file: &Path, line: usize) -> SourceMap {
+        SourceMap { runs: vec![(0, file.to_path_buf(), line)] }
+    }
+
+    /// File and line of an offset in `markdown`, the markdown the map is for
+    pub fn locate(&self, markdown: &str, offset: usize) -> (&Path, usize) {
+        let i = self.runs.partition_point(|(start, _, _)| *start <= offset).max(1) - 1;
+        let (start, file, line) = &self.runs[i];
+        (file, line + markdown[(*start).min(offset)..offset].matches('\n').count())
+    }
+
+    /// `file:line` of an offset in `markdown`, for error messages
+    pub fn position(&self, markdown: &str, offset: usize) -> String {
+        let (file, line) = self.locate(markdown, offset);
+        format!("{}:{}", file.display(), line)
+    }
+
+    /// The map of `markdown[range]`
+    pub fn slice(&self, markdown: &str, range: Range<usize>) -> SourceMap {
+        let mut map = SourceMap { runs: Vec::new() };
+        map.copy(0, self, markdown, range);
+        map
+    }
+
+    /// Records that `markdown[range]`, which `source` maps, was copied to
+    /// `offset` of the markdown this map is for. An empty range marks text
+    /// put in place of what is at its start, such as a placeholder.
+    pub fn copy(&mut self, offset: usize, source: &SourceMap, markdown: &str, range: Range<usize>) {
+        let (file, line) = source.locate(markdown, range.start);
+        self.push(offset, file, line);
+        for (start, file, line) in &source.runs {
+            if *start > range.start && *start < range.end {
+                self.push(offset + start - range.start, file, *line);
+            }
+        }
+    }
+
+    fn push(&mut self, offset: usize, file: &Path, line: usize) {
+        // A later run at the same offset replaces one whose text was empty
+        if self.runs.last().is_some_and(|(start, _, _)| *start == offset) {
+            self.runs.pop();
+        }
+        self.runs.push((offset, file.to_path_buf(), line));
+    }
+}
+
+/// Splices the includes into markdown from the file at the top of `stack`,
+/// starting at `line` of that file
+fn splice(
+    markdown: &str,
+    line: usize,
+    stack: &mut Vec<PathBuf>,
+    dependencies: &mut Vec<PathBuf>
+) -> Result<(String, SourceMap), Box<dyn std::error::Error>> {
+    let code = shortcodes::code_ranges(markdown);
+    let sources = SourceMap::new(&stack[stack.len() - 1], line);
+    let mut output = String::new();
+    let mut map = SourceMap { runs: Vec::new() };
+    let mut rest = 0;
+    let mut search = 0;
+
+    while let Some(start) = markdown[search..].find("{{").map(|i| search + i) {
+        search = start + 2;
+        let directive = markdown[start + 2..].trim_start();
+        let name = ["include", "code"].into_iter().find(|name| {
+            directive.strip_prefix(name).is_some_and(|args| args.starts_with(char::is_whitespace))
+        });
+        let name = match name {
+            Some(name) if !code.iter().any(|range| range.contains(&start)) => name,
+            _ => {
+                continue;
+            }
+        };
+
+        let position = sources.position(markdown, start);
+        let error = |message: String| -> Box<dyn std::error::Error> {
+            format!("{}: {}", position, message).into()
+        };
+
+        let end = match markdown[start..].find("}}") {
+            Some(i) => start + i + 2,
+            None => {
+                return Err(error(format!("unclosed {}, expected `}}}}`", name)));
+            }
+        };
+        let args = markdown[start + 2..end - 2].trim()[name.len()..].trim();
+        let (path, options) = include_path(args).ok_or_else(|| {
+            error(format!("expected `{{{{ {} \"path/to/file\" }}}}`", name))
+        })?;
+        let options = shortcodes::parse_args(options).map_err(&error)?;
+
+        let is_normal = Path::new(path).components().all(|component| matches!(component, Component::Normal(_)));
+        let path = PathBuf::from(path);
+        if !is_normal || !path.is_file() {
+            let kind = if name == "code" { "embedded" } else { "included" };
+            return Err(error(format!("{} file {} not found", kind, path.display())));
+        }
+        if stack.contains(&path) {
+            let cycle: Vec<String> = stack
+                .iter()
+                .chain([&path])
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(error(format!("include cycle: {}", cycle.join(" -> "))));
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        let (selected, first_line) = match (options.get("lines"), options.get("region")) {
+            (Some(lines), None) => select_lines(&contents, lines).map_err(&error)?,
+            (None, Some(region)) => select_region(&contents, region).ok_or_else(|| {
+                error(format!("no region \"{}\" in {}", region, path.display()))
+            })?,
+            (None, None) => (contents.as_str(), 1),
+            (Some(_), Some(_)) => {
+                return Err(error(format!("{} takes `lines` or `region`, not both", name)));
+            }
+        };
+
+        if !dependencies.contains(&path) {
+            dependencies.push(path.clone());
+        }
+        map.copy(output.len(), &sources, markdown, rest..start);
+        output.push_str(&markdown[rest..start]);
+        if name == "code" {
+            let lang = options
+                .get("lang")
+                .map(String::as_str)
+                .or(path.extension().and_then(|extension| extension.to_str()))
+                .unwrap_or("");
+            map.copy(output.len(), &sources, markdown, start..start);
+            output.push_str(&code_block(selected, lang));
+        } else {
+            stack.push(path);
+            let (included, included_sources) = splice(selected, first_line, stack, dependencies)?;
+            stack.pop();
+
+            let included = included.trim_end_matches('\n');
+            map.copy(output.len(), &included_sources, included, 0..included.len());
+            output.push_str(included);
+        }
+        rest = end;
+        search = end;
+    }
+    map.copy(output.len(), &sources, markdown, rest..markdown.len());
+    output.push_str(&markdown[rest..]);
+
+    Ok((output, map))
+}
+
+/// Splits `"path/to/file.md" lines="1-5"` into the quoted path and the rest
+fn include_path(args: &str) -> Option<(&str, &str)> {
+    let quoted = args.strip_prefix('"')?;
+    let end = quoted.find('"')?;
+    Some((&quoted[..end], &quoted[end + 1..]))
+}
+
+/// Lines `from-to` (1-based and inclusive, either end optional) of a file,
+/// with the line they start on
+fn select_lines<'a>(contents: &'a str, lines: &str) -> Result<(&'a str, usize), String> {
+    let invalid = || format!("invalid line range \"{}\", expected `from-to`", lines);
+    let (from, to) = lines.split_once('-').unwrap_or((lines, lines));
+    let from: usize = if from.trim().is_empty() { 1 } else { from.trim().parse().map_err(|_| invalid())? };
+    let to: usize = if to.trim().is_empty() { usize::MAX } else { to.trim().parse().map_err(|_| invalid())? };
+    if from == 0 || to < from {
+        return Err(invalid());
+    }
+
+    let offsets: Vec<usize> = contents
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            *offset += line.len();
+            Some(*offset)
+        })
+        .collect();
+    if from > offsets.len() {
+        return Err(format!("line range \"{}\" is past the end of the file", lines));
+    }
+
+    let start = if from == 1 { 0 } else { offsets[from - 2] };
+    let end = offsets[to.min(offsets.len()) - 1];
+    Ok((&contents[start..end], from))
+}
+
+/// The lines between `<!-- region name -->` and the next `<!-- endregion -->`,
+/// with the line they start on. The markers can be any kind of line comment.
+fn select_region<'a>(contents: &'a str, name: &str) -> Option<(&'a str, usize)> {
+    let mut start = None;
+    let mut offset = 0;
+
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        let marker: Vec<&str> = region_marker(line).map(|marker| marker.split_whitespace().collect()).unwrap_or_default();
+
+        match (start, marker.as_slice()) {
+            (None, ["region", region]) if *region == name => {
+                start = Some((offset + line.len(), i + 2));
+            }
+            (Some((start, first_line)), ["endregion", ..]) => {
+                return Some((&contents[start..offset], first_line));
+            }
+            _ => {}
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// The text of a comment on a line of its own: `<!-- region -->`, `// region`,
+/// `/* region */`, `# region`, `-- region` or `; region`
+fn region_marker(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let (open, close) = [("<!--", "-->"), ("/*", "*/"), ("//", ""), ("#", ""), ("--", ""), (";", "")]
+        .into_iter()
+        .find(|(open, close)| line.starts_with(open) && line.ends_with(close))?;
+
+    line.get(open.len()..line.len() - close.len())
+}
+
+/// A fenced code block holding `code`, dedented, with a fence longer than any
+/// run of backticks in it
+fn code_block(code: &str, lang: &str) -> String {
+    let indent = code
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    let mut block = format!("{}{}\n", fence, lang);
+    for line in code.lines() {
+        block.push_str(line.get(indent..).unwrap_or("").trim_end());
+        block.push('\n');
+    }
+    block.push_str(&fence);
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "one\ntwo\nthree\nfour\n";
+
+    #[test]
+    fn select_lines_takes_inclusive_ranges() {
+        assert_eq!(select_lines(CONTENTS, "2-3"), Ok(("two\nthree\n", 2)));
+        assert_eq!(select_lines(CONTENTS, "3-"), Ok(("three\nfour\n", 3)));
+        assert_eq!(select_lines(CONTENTS, "-1"), Ok(("one\n", 1)));
+        assert_eq!(select_lines(CONTENTS, "2"), Ok(("two\n", 2)));
+        assert_eq!(select_lines(CONTENTS, "3-99"), Ok(("three\nfour\n", 3)));
+    }
+
+    #[test]
+    fn select_lines_rejects_invalid_ranges() {
+        assert!(select_lines(CONTENTS, "0-2").is_err());
+        assert!(select_lines(CONTENTS, "3-2").is_err());
+        assert!(select_lines(CONTENTS, "a-b").is_err());
+        assert!(select_lines(CONTENTS, "5-").is_err());
+    }
+
+    #[test]
+    fn select_region_accepts_any_line_comment() {
+        let contents = "a\n<!-- region linux -->\nb\nc\n<!-- endregion -->\nd\n";
+        assert_eq!(select_region(contents, "linux"), Some(("b\nc\n", 3)));
+        assert_eq!(select_region(contents, "windows"), None);
+
+        let contents = "fn main() {\n    // region setup\n    let x = 1;\n    // endregion\n}\n";
+        assert_eq!(select_region(contents, "setup"), Some(("    let x = 1;\n", 3)));
+        assert_eq!(select_region("# region a\nx\n# endregion\n", "a"), Some(("x\n", 2)));
+    }
+
+    #[test]
+    fn code_block_dedents_and_outgrows_backtick_runs() {
+        assert_eq!(code_block("    a\n      b\n", "rust"), "```rust\na\n  b\n```");
+        assert_eq!(code_block("x ```` y\n", ""), "`````\nx ```` y\n`````");
+    }
+
+    #[test]
+    fn includes_in_code_are_left_alone() {
+        let markdown = "Write `{{ include \"a.md\" }}` or ``{{ code \"b.rs\" }}``.\n\n    {{ include \"c.md\" }}\n";
+        let (output, _) = splice(markdown, 1, &mut vec![PathBuf::from("page.md")], &mut Vec::new()).unwrap();
+        assert_eq!(output, markdown);
+
+        let markdown = "text\n{{ include \"missing.md\" }}\n";
+        let error = splice(markdown, 1, &mut vec![PathBuf::from("page.md")], &mut Vec::new()).unwrap_err().to_string();
+        assert!(error.starts_with("page.md:2: "), "{}", error);
+    }
+
+    #[test]
+    fn source_map_follows_copied_text() {
+        let page = SourceMap::new(Path::new("page.md"), 5);
+        let snippet = SourceMap::new(Path::new("snippet.md"), 3);
+
+        // "a\n" from the page, "s1\ns2" from the snippet, then "\nb\n" from
+        // line 6 of the page
+        let mut map = SourceMap { runs: Vec::new() };
+        map.copy(0, &page, "a\n{{ include }}\nb\n", 0..2);
+        map.copy(2, &snippet, "s1\ns2", 0..5);
+        map.copy(7, &page, "a\n{{ include }}\nb\n", 15..18);
+        let markdown = "a\ns1\ns2\nb\n";
+
+        assert_eq!(map.locate(markdown, 0), (Path::new("page.md"), 5));
+        assert_eq!(map.locate(markdown, 2), (Path::new("snippet.md"), 3));
+        assert_eq!(map.locate(markdown, 5), (Path::new("snippet.md"), 4));
+        assert_eq!(map.locate(markdown, 8), (Path::new("page.md"), 7));
+        assert_eq!(map.slice(markdown, 5..9).position("s2\nb", 3), "page.md:7");
+    }
+}
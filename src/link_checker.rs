@@ -0,0 +1,368 @@
+// src/link_checker.rs
+
+use pulldown_cmark::{ Event, Options, Parser, Tag };
+use std::collections::{ HashMap, HashSet };
+use std::fmt;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use crate::asset_handler::AssetManifest;
+use crate::build_handler;
+use crate::config::{ self, CheckConfig, Config };
+use crate::data;
+use crate::generate;
+use crate::includes;
+use crate::page::{ self, Page };
+use crate::render;
+use crate::shortcodes;
+
+/// A link or image that doesn't resolve, and where it was written
+#[derive(Debug)]
+pub struct BrokenLink {
+    pub file: PathBuf,
+    pub line: usize,
+    pub link: String,
+    pub reason: String,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {} ({})", self.file.display(), self.line, self.link, self.reason)
+    }
+}
+
+/// What links can point at: the built pages with their element ids, and the
+/// rest of the output tree
+struct Site<'a> {
+    config: &'a Config,
+    /// Element ids on each page, by page URL
+    pages: HashMap<String, HashSet<String>>,
+    /// URL prefixes of external links known to be good
+    allowlist: Vec<String>,
+}
+
+/// Checks the links and images in the template, the menus and every
+/// rendered page against the route index and the build output in `out/`, so
+/// it must run after a build. Broken links are reported where they were
+/// written.
+///
+/// External links are only checked when `check.external` is set, and then
+/// against the allowlist file rather than over the network.
+pub fn check_links(config: &Config, assets: &AssetManifest) -> Result<Vec<BrokenLink>, Box<dyn std::error::Error>> {
+    let pages = page::load_pages(config)?;
+    let mut site = Site {
+        config,
+        pages: HashMap::new(),
+        allowlist: load_allowlist(&config.check)?,
+    };
+    for page in &pages {
+        let html = fs::read_to_string(build_handler::output_path(&page.url))?;
+        site.pages.insert(page.url.clone(), html_ids(&html));
+    }
+
+    let mut broken_links = Vec::new();
+
+    // The template is the same on every page, so its links are checked once
+    let template = fs::read_to_string("template.html")?;
+    for (line, link) in template_links(&template) {
+        let url = match link.strip_prefix("{{asset(\"").and_then(|link| link.strip_suffix("\")}}")) {
+            Some(path) => assets.url(path),
+            None if link.contains("{{") => {
+                continue;
+            }
+            None => link.to_owned(),
+        };
+        if let Err(reason) = site.check(&url, "/") {
+            broken_links.push(BrokenLink { file: PathBuf::from("template.html"), line, link: link.to_owned(), reason });
+        }
+    }
+
+    // Menus fill in links the template would otherwise hold, so check their
+    // items too: those from the config, and pages listing themselves
+    let config_file = fs::read_to_string(config::CONFIG_FILE).unwrap_or_default();
+    for item in config.menus.values().flatten() {
+        if let Err(reason) = site.check(&item.url, "/") {
+            let line = config_file
+                .lines()
+                .position(|line| line.contains(&format!("\"{}\"", item.url)))
+                .map_or(1, |i| i + 1);
+            broken_links.push(BrokenLink {
+                file: PathBuf::from(config::CONFIG_FILE),
+                line,
+                link: item.url.clone(),
+                reason,
+            });
+        }
+    }
+    for page in pages.iter().filter(|page| !page.front_matter.menu.is_empty()) {
+        if let Err(reason) = site.check(&page.url, "/") {
+            broken_links.push(BrokenLink { file: page.path.clone(), line: 1, link: page.url.clone(), reason });
+        }
+    }
+
+    // Pages are checked as they are rendered, so links from included files,
+    // shortcode templates and the templates of generated pages are checked too
+    for page in &pages {
+        let html = render::render_markdown(page, config)?;
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        for link in html_links(&html) {
+            let occurrence = occurrences.entry(link.clone()).or_default();
+            *occurrence += 1;
+
+            if let Err(reason) = site.check(&link, &page.url) {
+                let (file, line) = link_position(&link, *occurrence, page, config)?;
+                broken_links.push(BrokenLink { file, line, link, reason });
+            }
+        }
+    }
+
+    // A link in a file that several pages are rendered from, such as the
+    // template of generated pages, is reported once
+    let mut seen = HashSet::new();
+    broken_links.retain(|broken| seen.insert((broken.file.clone(), broken.line, broken.link.clone())));
+
+    Ok(broken_links)
+}
+
+impl Site<'_> {
+    /// Checks a link found on the page at `page_url`
+    fn check(&self, link: &str, page_url: &str) -> Result<(), String> {
+        if link.starts_with("http://") || link.starts_with("https://") || link.starts_with("//") {
+            return self.check_external(link);
+        }
+        // Other schemes, such as mailto: and tel:
+        if link.split('/').next().unwrap_or("").contains(':') {
+            return Ok(());
+        }
+
+        let (target, fragment) = link.split_once('#').unwrap_or((link, ""));
+        let target = target.split('?').next().unwrap_or("");
+        let url = if target.is_empty() {
+            page_url.to_owned()
+        } else {
+            resolve(page_url, target)
+        };
+
+        if let Some(ids) = self.pages.get(&page::canonical_url(&url, self.config)) {
+            if !fragment.is_empty() && !ids.contains(fragment) {
+                return Err(format!("no #{} anchor on {}", fragment, url));
+            }
+            return Ok(());
+        }
+        if Path::new("out").join(url.trim_start_matches('/')).is_file() {
+            return Ok(());
+        }
+
+        Err(format!("no page or file at {}", url))
+    }
+
+    fn check_external(&self, link: &str) -> Result<(), String> {
+        if !self.config.check.external || self.allowlist.iter().any(|allowed| link.starts_with(allowed)) {
+            return Ok(());
+        }
+
+        Err(format!("external link not in {}", self.config.check.allowlist))
+    }
+}
+
+/// Resolves a link against the URL of the page it is on, the way a browser
+/// would: `../b` on `/docs/a` is `/b`, but on `/docs/a/` (a pretty URL, which
+/// browsers treat as a directory) it is `/docs/b`
+fn resolve(page_url: &str, target: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    if !target.starts_with('/') {
+        segments.extend(page_url.split('/').filter(|segment| !segment.is_empty()));
+        if !page_url.ends_with('/') {
+            segments.pop();
+        }
+    }
+
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let trailing_slash = if target.ends_with('/') && !segments.is_empty() { "/" } else { "" };
+    format!("/{}{}", segments.join("/"), trailing_slash)
+}
+
+/// Values of the `id` and `name` attributes in a page, quoted or not
+fn html_ids(html: &str) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    for attribute in [" id=", " name="] {
+        for (start, _) in html.match_indices(attribute) {
+            let value = &html[start + attribute.len()..];
+            let id = match value.strip_prefix('"') {
+                Some(value) => value.split('"').next(),
+                None => value.split(|c: char| c.is_whitespace() || c == '>').next(),
+            };
+            ids.extend(id.map(str::to_owned));
+        }
+    }
+
+    ids
+}
+
+/// Values of the `href` and `src` attributes in rendered HTML, quoted or not,
+/// decoded and in document order
+fn html_links(html: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for attribute in [" href=", " src="] {
+        for (start, _) in html.match_indices(attribute) {
+            // Skip text that only looks like an attribute, such as escaped
+            // HTML in a code block
+            let before = &html[..start];
+            if before.rfind('<') < before.rfind('>') {
+                continue;
+            }
+
+            let value = &html[start + attribute.len()..];
+            let link = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+                _ => value.split(|c: char| c.is_whitespace() || c == '>').next(),
+            };
+            links.extend(link.map(|link| (start, render::unescape(link))));
+        }
+    }
+    links.sort();
+
+    links.into_iter().map(|(_, link)| link).collect()
+}
+
+/// Where the `occurrence`-th copy of a link on a rendered page was written:
+/// a markdown link or image in the page or a file it includes, other quoted
+/// text there (such as a shortcode argument or raw HTML), a shortcode
+/// template, or the template or data file of a generated page. Falls back to
+/// the start of the page's body.
+fn link_position(
+    link: &str,
+    occurrence: usize,
+    page: &Page,
+    config: &Config
+) -> Result<(PathBuf, usize), Box<dyn std::error::Error>> {
+    let quoted = [format!("\"{}\"", link), format!("'{}'", link)];
+
+    if page.html.is_none() {
+        let (markdown, sources) = includes::expand_with_sources(page)?;
+        let markdown_links: Vec<usize> = Parser::new_ext(&markdown, Options::ENABLE_STRIKETHROUGH)
+            .into_offset_iter()
+            .filter_map(|(event, range)| match event {
+                Event::Start(Tag::Link(_, dest, _) | Tag::Image(_, dest, _)) => {
+                    let rendered = page.bundle_asset(&dest).map(|(_, url)| url);
+                    (*dest == *link || rendered.as_deref() == Some(link)).then_some(range.start)
+                }
+                _ => None,
+            })
+            .collect();
+        let quoted_text: Vec<usize> = quoted
+            .iter()
+            .flat_map(|quoted| markdown.match_indices(quoted.as_str()).map(|(i, _)| i + 1))
+            .collect();
+
+        for offsets in [markdown_links, quoted_text] {
+            if let Some(offset) = offsets.get(occurrence - 1).or(offsets.first()) {
+                let (file, line) = sources.locate(&markdown, *offset);
+                return Ok((file.to_path_buf(), line));
+            }
+        }
+    }
+
+    let mut templates: Vec<PathBuf> = config.generate
+        .iter()
+        .filter(|generate| page.path == Path::new(data::DATA_DIR).join(&generate.data))
+        .map(|generate| Path::new(generate::TEMPLATES_DIR).join(&generate.template))
+        .collect();
+    if page.html.is_some() {
+        templates.push(page.path.clone());
+    }
+    if let Ok(entries) = fs::read_dir(shortcodes::SHORTCODES_DIR) {
+        let mut entries: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        entries.sort();
+        templates.extend(entries);
+    }
+    for template in templates {
+        let Ok(contents) = fs::read_to_string(&template) else {
+            continue;
+        };
+        let line = contents
+            .lines()
+            .position(|line| quoted.iter().any(|quoted| line.contains(quoted.as_str())) || line.contains(link));
+        if let Some(line) = line {
+            return Ok((template, line + 1));
+        }
+    }
+
+    Ok((page.path.clone(), page.content_line))
+}
+
+/// `href` and `src` attribute values in the template, with their line numbers
+fn template_links(template: &str) -> Vec<(usize, &str)> {
+    let mut links = Vec::new();
+    for (i, line) in template.lines().enumerate() {
+        for attribute in ["href=\"", "src=\""] {
+            for (start, _) in line.match_indices(attribute) {
+                let value = &line[start + attribute.len()..];
+                // Template helpers such as {{asset("styles.css")}} contain quotes
+                let end = match value.find("}}") {
+                    Some(end) if value.starts_with("{{") => end + 2,
+                    _ => value.find('"').unwrap_or(value.len()),
+                };
+                links.push((i + 1, &value[..end]));
+            }
+        }
+    }
+
+    links
+}
+
+/// Reads the allowlist: one URL or URL prefix per line, with `#` comments
+fn load_allowlist(options: &CheckConfig) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if !options.external || !Path::new(&options.allowlist).is_file() {
+        return Ok(Vec::new());
+    }
+
+    Ok(
+        fs::read_to_string(&options.allowlist)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_drops_the_last_segment_of_html_urls() {
+        assert_eq!(resolve("/docs/a", "b"), "/docs/b");
+        assert_eq!(resolve("/docs/a", "../b"), "/b");
+        assert_eq!(resolve("/docs/a", "./b/c"), "/docs/b/c");
+        assert_eq!(resolve("/docs/a", "/x/y"), "/x/y");
+        assert_eq!(resolve("/", "about"), "/about");
+        assert_eq!(resolve("/docs/a", "../../.."), "/");
+    }
+
+    #[test]
+    fn resolve_treats_pretty_urls_as_directories() {
+        assert_eq!(resolve("/docs/a/", "b"), "/docs/a/b");
+        assert_eq!(resolve("/docs/c/", "../b/"), "/docs/b/");
+        assert_eq!(resolve("/docs/a/", "../../x"), "/x");
+        assert_eq!(resolve("/docs/a/", "/x/"), "/x/");
+    }
+
+    #[test]
+    fn html_links_reads_attributes_in_document_order() {
+        let html = concat!(
+            r#"<p><img src="/a.png" alt=""><a href='/b?x=1&amp;y=2'>b</a> <a href=/c>c</a></p>"#,
+            r#"<pre><code>&lt;a href=&quot;/in-code&quot;&gt;</code></pre>"#
+        );
+        assert_eq!(html_links(html), vec!["/a.png", "/b?x=1&y=2", "/c"]);
+    }
+}
@@ -2,6 +2,7 @@
 
 use actix_web::{ web, App, HttpServer, middleware::Logger };
 use actix_web::middleware::Compress;
+use clap::{ App as Cli, Arg, SubCommand };
 use env_logger::Env;
 
 mod root_handler;
@@ -9,31 +10,145 @@ mod page_handler;
 mod sitemap_handler;
 mod build_handler;
 mod robots_handler;
+mod redirects_handler;
 mod api_handler;
+mod admonitions;
+mod asset_handler;
+mod config;
+mod data;
+mod diagrams;
+mod generate;
+mod highlight;
+mod icons;
+mod images;
+mod includes;
+mod link_checker;
+mod math;
+mod navigation;
+mod page;
+mod render;
+mod search_handler;
+mod seo;
+mod shortcodes;
+mod summary;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Set up logger
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
+    let matches = Cli::new("zipity")
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Builds the static site into out/")
+                .arg(Arg::with_name("minify").long("minify").help("Minifies HTML, CSS and JavaScript"))
+        )
+        .subcommand(SubCommand::with_name("check").about("Builds the site and checks it for broken links"))
+        .get_matches();
+
+    // Load the site configuration from zipity.toml
+    let mut config = config::load_config().unwrap();
+
+    if let Some(build_matches) = matches.subcommand_matches("build") {
+        if build_matches.is_present("minify") {
+            config.build.minify = true;
+            config.build.minify_html = true;
+        }
+
+        let summary = build_or_exit(&config);
+        if summary.unchanged > 0 {
+            println!("Built {} pages in out/ ({} unchanged)", summary.pages, summary.unchanged);
+        } else {
+            println!("Built {} pages in out/", summary.pages);
+        }
+        if config.build.minify_html && summary.html_bytes > 0 {
+            let saved = summary.html_bytes - summary.minified_html_bytes;
+            println!(
+                "Minified HTML: {} bytes -> {} bytes ({:.1}% smaller)",
+                summary.html_bytes,
+                summary.minified_html_bytes,
+                ((saved as f64) / (summary.html_bytes as f64)) * 100.0
+            );
+        }
+        report_broken_links(&summary, true);
+        return Ok(());
+    }
+
+    if matches.subcommand_matches("check").is_some() {
+        config.build.check_links = true;
+
+        let summary = build_or_exit(&config);
+        report_broken_links(&summary, true);
+        println!("No broken links in {} pages", summary.pages);
+        return Ok(());
+    }
+
     println!("Server is running on http://127.0.0.1:8080");
 
     // Build the static HTML files
-    build_handler::build_static_files().unwrap();
+    let summary = build_handler::build_static_files(&config).unwrap();
+    report_broken_links(&summary, false);
+
+    // Build the sitemap, robots.txt, redirects, search index and page listings once instead of on every request,
+    // and load the asset manifest written by the build
+    let sitemap = web::Data::new(sitemap_handler::build_sitemap(&config).unwrap());
+    let robots = web::Data::new(robots_handler::build_robots(&config).unwrap());
+    let redirects = web::Data::new(redirects_handler::build_redirects(&config).unwrap());
+    let search_index = web::Data::new(search_handler::build_search_index(&config).unwrap());
+    let listings = web::Data::new(api_handler::build_page_listings(&config).unwrap());
+    let assets = web::Data::new(asset_handler::load_manifest(&config.build).unwrap());
+    let config = web::Data::new(config);
 
     // Start the server
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         App::new()
             .wrap(Compress::default()) // Enable compression middleware
             .wrap(Logger::default()) // Enable logger middleware
+            .app_data(sitemap.clone())
+            .app_data(robots.clone())
+            .app_data(redirects.clone())
+            .app_data(search_index.clone())
+            .app_data(listings.clone())
+            .app_data(assets.clone())
+            .app_data(config.clone())
             .service(web::resource("/").route(web::get().to(root_handler::root)))
             .service(web::resource("/sitemap.xml").route(web::get().to(sitemap_handler::sitemap)))
+            .service(
+                web::resource("/sitemap-{index}.xml").route(
+                    web::get().to(sitemap_handler::sitemap_part)
+                )
+            )
             .service(web::resource("/robots.txt").route(web::get().to(robots_handler::robots)))
-            .service(web::resource("/{name}").route(web::get().to(page_handler::page)))
-            .service(actix_files::Files::new("/static", "./static").show_files_listing())
+            .service(web::resource("/static/{path:.*}").route(web::get().to(asset_handler::asset)))
+            .service(web::resource("/api/search").route(web::get().to(search_handler::search)))
+            .service(web::resource("/api/pages").route(web::get().to(api_handler::pages)))
             .service(web::resource("/api/{name}").route(web::get().to(api_handler::api_handler)))
-            .service(actix_files::Files::new("/static", "./out").show_files_listing()) // Or use "./dist" if you prefer
+            .service(web::resource("/{path:.*}").route(web::get().to(page_handler::page)))
     })
         .bind("127.0.0.1:8080")?
         .run().await
 }
+
+/// Builds the site, exiting with the error (such as a broken `@/` link) when
+/// the build fails
+fn build_or_exit(config: &config::Config) -> build_handler::BuildSummary {
+    match build_handler::build_static_files(config) {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("Build failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints each broken link as `file:line: link (reason)`. Broken links fail
+/// `build` and `check`, but only warn when serving.
+fn report_broken_links(summary: &build_handler::BuildSummary, fail: bool) {
+    for broken_link in &summary.broken_links {
+        eprintln!("{}", broken_link);
+    }
+    if fail && !summary.broken_links.is_empty() {
+        eprintln!("Found {} broken links", summary.broken_links.len());
+        std::process::exit(1);
+    }
+}
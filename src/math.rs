@@ -0,0 +1,219 @@
+// src/math.rs
+
+use katex::{ Opts, OutputType };
+use crate::config::{ Config, MathConfig };
+use crate::includes::SourceMap;
+use crate::shortcodes;
+
+// Math is swapped for `PLACEHOLDER_START<index>PLACEHOLDER_END` before the
+// markdown is parsed, like shortcodes but with markers of its own
+const PLACEHOLDER_START: char = '\u{E002}';
+const PLACEHOLDER_END: char = '\u{E003}';
+
+/// Renders the `$inline$` and `$$display$$` math in a piece of a page's
+/// markdown with KaTeX, replacing it with placeholders. `sources` maps the
+/// piece to where it was written. Returns the markdown with its own map, and
+/// the HTML of each formula, for `insert`.
+///
+/// An inline formula can't start or end with whitespace, and a `$` followed by
+/// a digit doesn't close one, so prices like $5 and $10 stay text. Escaped
+/// dollars (`\$`) and code are left alone. Invalid TeX is an error.
+pub fn expand(
+    markdown: &str,
+    sources: &SourceMap,
+    config: &Config
+) -> Result<(String, SourceMap, Vec<String>), Box<dyn std::error::Error>> {
+    let bytes = markdown.as_bytes();
+    let code_blocks = shortcodes::fenced_code_blocks(markdown);
+    let mut output = String::new();
+    let mut map = sources.slice(markdown, 0..0);
+    let mut html = Vec::new();
+    let mut rest = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(block) = code_blocks.iter().find(|block| block.contains(&i)) {
+            i = block.end;
+            continue;
+        }
+
+        let formula = match bytes[i] {
+            b'\\' => {
+                i += 2;
+                continue;
+            }
+            b'`' => {
+                i = code_span_end(markdown, i);
+                continue;
+            }
+            b'$' if bytes.get(i + 1) == Some(&b'$') => {
+                markdown[i + 2..].find("$$").map(|end| (&markdown[i + 2..i + 2 + end], true, i + 2 + end + 2))
+            }
+            b'$' => inline_formula(markdown, i).map(|(tex, end)| (tex, false, end)),
+            _ => None,
+        };
+
+        match formula {
+            Some((tex, display, end)) => {
+                let rendered = render(tex, display, &config.math).map_err(|e| {
+                    let delimiter = if display { "$$" } else { "$" };
+                    format!(
+                        "{}: invalid TeX in {}{}{}: {}",
+                        sources.position(markdown, i),
+                        delimiter,
+                        tex.trim(),
+                        delimiter,
+                        e
+                    )
+                })?;
+
+                map.copy(output.len(), sources, markdown, rest..i);
+                output.push_str(&markdown[rest..i]);
+                map.copy(output.len(), sources, markdown, i..i);
+                output.push(PLACEHOLDER_START);
+                output.push_str(&html.len().to_string());
+                output.push(PLACEHOLDER_END);
+                html.push(rendered);
+                rest = end;
+                i = end;
+            }
+            None => {
+                i += 1;
+            }
+        }
+    }
+    map.copy(output.len(), sources, markdown, rest..markdown.len());
+    output.push_str(&markdown[rest..]);
+
+    Ok((output, map, html))
+}
+
+/// Puts the HTML of each formula back in place of its placeholder. Display
+/// math on its own line replaces the paragraph markdown wrapped it in.
+pub fn insert(html_output: &str, formulas: &[String]) -> String {
+    let mut html_output = html_output.to_owned();
+    for (i, html) in formulas.iter().enumerate() {
+        let placeholder = format!("{}{}{}", PLACEHOLDER_START, i, PLACEHOLDER_END);
+        html_output = html_output
+            .replace(&format!("<p>{}</p>", placeholder), html)
+            .replace(&placeholder, html);
+    }
+
+    html_output
+}
+
+/// TeX and end offset of an inline formula starting with the `$` at `start`.
+/// The formula ends at the next unescaped `$`, which must be a valid closing
+/// one, and can't run into code or past its paragraph.
+fn inline_formula(markdown: &str, start: usize) -> Option<(&str, usize)> {
+    let rest = &markdown[start + 1..];
+    if rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut search = 0;
+    let end = loop {
+        let end = search + rest[search..].find('$')?;
+        if !rest[..end].ends_with('\\') {
+            break end;
+        }
+        search = end + 1;
+    };
+
+    let tex = &rest[..end];
+    let is_closing = !tex.is_empty() &&
+        !tex.ends_with(char::is_whitespace) &&
+        !rest[end + 1..].starts_with(|c: char| c.is_ascii_digit());
+    if !is_closing || tex.contains('`') || tex.contains("\n\n") {
+        return None;
+    }
+
+    Some((tex, start + 1 + end + 1))
+}
+
+/// End of the code span starting with the backtick run at `start`, or of the
+/// run itself when it isn't closed
+fn code_span_end(markdown: &str, start: usize) -> usize {
+    let run = markdown[start..].len() - markdown[start..].trim_start_matches('`').len();
+    let fence = &markdown[start..start + run];
+
+    let mut search = start + run;
+    while let Some(i) = markdown[search..].find(fence) {
+        let end = search + i;
+        let closing_run = markdown[end..].len() - markdown[end..].trim_start_matches('`').len();
+        if closing_run == run {
+            return end + run;
+        }
+        search = end + closing_run;
+    }
+
+    start + run
+}
+
+fn render(tex: &str, display: bool, options: &MathConfig) -> Result<String, String> {
+    let output_type = match options.output.as_str() {
+        "mathml" => OutputType::Mathml,
+        "html" => OutputType::Html,
+        "html_and_mathml" => OutputType::HtmlAndMathml,
+        output => {
+            return Err(format!("unknown math.output \"{}\"", output));
+        }
+    };
+
+    let mut opts = Opts::builder()
+        .display_mode(display)
+        .output_type(output_type)
+        .throw_on_error(true)
+        .build()
+        .map_err(|e| e.to_string())?;
+    for (name, expansion) in &options.macros {
+        opts.add_macro(name.clone(), expansion.clone());
+    }
+
+    katex::render_with_opts(tex, &opts).map_err(|e| match e {
+        katex::Error::JsExecError(message) => parse_error(&message),
+        e => e.to_string(),
+    })
+}
+
+/// KaTeX's own message from a failed render, such as `Undefined control
+/// sequence: \foo at position 2`, without the JavaScript wrapping
+fn parse_error(message: &str) -> String {
+    let message = message
+        .trim_start_matches("String(\"")
+        .trim_end_matches("\")")
+        .trim_start_matches("ParseError: ")
+        .trim_start_matches("KaTeX parse error: ");
+    let message = message.replace("\\\\", "\\");
+
+    // Drop the excerpt of the formula after the position
+    match message.find(" at position ") {
+        Some(i) => {
+            let position = &message[i..];
+            message[..i].to_owned() + &position[..position.find(':').unwrap_or(position.len())]
+        }
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_formula_ends_at_a_closing_dollar() {
+        assert_eq!(inline_formula("$x^2$ is", 0), Some(("x^2", 5)));
+        assert_eq!(inline_formula(r"$a \$ b$", 0), Some((r"a \$ b", 8)));
+        assert_eq!(inline_formula("is $x$.", 3), Some(("x", 6)));
+    }
+
+    #[test]
+    fn inline_formula_leaves_prices_and_spaced_dollars() {
+        assert_eq!(inline_formula("$5 and $10", 0), None);
+        assert_eq!(inline_formula("$ x$", 0), None);
+        assert_eq!(inline_formula("$x $", 0), None);
+        assert_eq!(inline_formula("$x\n\ny$", 0), None);
+        assert_eq!(inline_formula("$`x`$", 0), None);
+        assert_eq!(inline_formula("$$", 0), None);
+    }
+}
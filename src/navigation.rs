@@ -0,0 +1,218 @@
+// src/navigation.rs
+
+use std::collections::{ BTreeMap, BTreeSet, HashMap };
+use crate::config::Config;
+use crate::page::{ self, Page };
+use crate::render::escape;
+
+/// `<li>` items of the menu `name` for a page, used in templates as
+/// `{{menu.name}}`. The menu holds the `[[menus.name]]` items from the config
+/// and the pages in the page's language with `menu: { name: { weight: 10 } }`
+/// in their front matter, ordered by weight. Names of items from the config
+/// are looked up in lowercase in the page language's strings (`home` for an
+/// item named `Home`), and their URLs written in the configured URL style
+/// when they are pages. The item for the page itself is marked
+/// `class="active"`, and items for sections containing it `class="active-trail"`.
+pub fn menu_html(name: &str, page: &Page, pages: &[Page], config: &Config) -> String {
+    let mut items: Vec<(i32, &str, &str)> = config.menus
+        .get(name)
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| {
+                    let name = config.translate_or(&page.lang, &item.name.to_lowercase(), &item.name);
+                    (item.weight, name, item.url.as_str())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    for other in pages.iter().filter(|other| other.lang == page.lang) {
+        if let Some(entry) = other.front_matter.menu.get(name) {
+            let name = entry.name.as_deref().unwrap_or(&other.front_matter.title);
+            items.push((entry.weight, name, &other.url));
+        }
+    }
+    items.sort_by_key(|(weight, _, _)| *weight);
+
+    items
+        .iter()
+        .map(|(_, name, url)| {
+            // Link to pages at their canonical URL, so that `/about` in the
+            // config doesn't cost a redirect with pretty URLs
+            let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+            let canonical = page::canonical_url(path, config);
+            let path = if path.starts_with('/') && pages.iter().any(|other| other.url == canonical) {
+                canonical.as_str()
+            } else {
+                path
+            };
+
+            let (class, current) = if path == page.url {
+                (r#" class="active""#, r#" aria-current="page""#)
+            } else if
+                path != page.home_url() &&
+                page.url.starts_with(&format!("{}/", path.trim_end_matches('/')))
+            {
+                (r#" class="active-trail""#, "")
+            } else {
+                ("", "")
+            };
+            let href = format!("{}{}", path, suffix);
+            format!(r#"<li{}><a href="{}"{}>{}</a></li>"#, class, escape(&href), current, escape(name))
+        })
+        .collect()
+}
+
+/// Nested lists of every page in a page's language, following the routes
+/// directory (`{{sections}}`). Directories without an `index.md` are shown
+/// by name, without a link.
+pub fn sections_html(page: &Page, pages: &[Page]) -> String {
+    let pages = pages_by_key(page, pages);
+    let mut keys = BTreeSet::new();
+    for key in pages.keys().filter(|key| !key.is_empty()) {
+        let mut key = *key;
+        keys.insert(key);
+        while let Some((parent, _)) = key.rsplit_once('/') {
+            keys.insert(parent);
+            key = parent;
+        }
+    }
+
+    let mut children: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for key in keys {
+        children.entry(parent_key(key)).or_default().push(key);
+    }
+    for keys in children.values_mut() {
+        keys.sort_by_key(|key| sort_key(key, &pages));
+    }
+
+    format!(r#"<nav class="sections">{}</nav>"#, section_list("", &children, &pages, page))
+}
+
+fn section_list(
+    parent: &str,
+    children: &BTreeMap<&str, Vec<&str>>,
+    pages: &HashMap<&str, &Page>,
+    page: &Page
+) -> String {
+    let Some(keys) = children.get(parent) else {
+        return String::new();
+    };
+
+    let mut html = String::from("<ul>");
+    for key in keys {
+        let class = if *key == page.key {
+            r#" class="active""#
+        } else if page.key.starts_with(&format!("{}/", key)) {
+            r#" class="active-trail""#
+        } else {
+            ""
+        };
+        let label = match pages.get(key) {
+            Some(other) if other.key == page.key => {
+                format!(r#"<a href="{}" aria-current="page">{}</a>"#, escape(&other.url), escape(&other.front_matter.title))
+            }
+            Some(other) => format!(r#"<a href="{}">{}</a>"#, escape(&other.url), escape(&other.front_matter.title)),
+            None => format!("<span>{}</span>", escape(segment(key))),
+        };
+        html.push_str(&format!("<li{}>{}{}</li>", class, label, section_list(key, children, pages, page)));
+    }
+    html.push_str("</ul>");
+
+    html
+}
+
+/// Trail of links from the home page down to a page (`{{breadcrumbs}}`),
+/// empty on the home page itself
+pub fn breadcrumbs_html(page: &Page, pages: &[Page], config: &Config) -> String {
+    if page.key.is_empty() {
+        return String::new();
+    }
+
+    let pages = pages_by_key(page, pages);
+    let mut crumbs = format!(
+        r#"<li><a href="{}">{}</a></li>"#,
+        escape(page.home_url()),
+        escape(config.translate_or(&page.lang, "home", "Home"))
+    );
+    let mut key = page.key.as_str();
+    let mut ancestors = Vec::new();
+    while let Some((parent, _)) = key.rsplit_once('/') {
+        ancestors.push(parent);
+        key = parent;
+    }
+    for key in ancestors.into_iter().rev() {
+        crumbs.push_str(
+            &(match pages.get(key) {
+                Some(other) => {
+                    format!(r#"<li><a href="{}">{}</a></li>"#, escape(&other.url), escape(&other.front_matter.title))
+                }
+                None => format!("<li>{}</li>", escape(segment(key))),
+            })
+        );
+    }
+    crumbs.push_str(&format!(r#"<li aria-current="page">{}</li>"#, escape(&page.front_matter.title)));
+
+    format!(r#"<nav class="breadcrumbs" aria-label="Breadcrumbs"><ol>{}</ol></nav>"#, crumbs)
+}
+
+/// Links to the previous and next pages in the same section, in section tree
+/// order (`{{prev_next}}`)
+pub fn prev_next_html(page: &Page, pages: &[Page]) -> String {
+    if page.key.is_empty() {
+        return String::new();
+    }
+
+    let pages = pages_by_key(page, pages);
+    let mut siblings: Vec<&str> = pages
+        .keys()
+        .copied()
+        .filter(|key| !key.is_empty() && parent_key(key) == parent_key(&page.key))
+        .collect();
+    siblings.sort_by_key(|key| sort_key(key, &pages));
+
+    let Some(i) = siblings.iter().position(|key| *key == page.key) else {
+        return String::new();
+    };
+    let link = |key: Option<&&str>, rel: &str| {
+        key.and_then(|key| pages.get(key))
+            .map(|other| {
+                format!(r#"<a rel="{}" href="{}">{}</a>"#, rel, escape(&other.url), escape(&other.front_matter.title))
+            })
+            .unwrap_or_default()
+    };
+    let prev = link(i.checked_sub(1).and_then(|i| siblings.get(i)), "prev");
+    let next = link(siblings.get(i + 1), "next");
+    if prev.is_empty() && next.is_empty() {
+        return String::new();
+    }
+
+    format!(r#"<nav class="prev-next">{}{}</nav>"#, prev, next)
+}
+
+/// The pages in a page's language, by route key
+fn pages_by_key<'a>(page: &Page, pages: &'a [Page]) -> HashMap<&'a str, &'a Page> {
+    pages
+        .iter()
+        .filter(|other| other.lang == page.lang)
+        .map(|other| (other.key.as_str(), other))
+        .collect()
+}
+
+/// Key of the section containing a route key, `""` for the top level
+fn parent_key(key: &str) -> &str {
+    key.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("")
+}
+
+/// Last segment of a route key
+fn segment(key: &str) -> &str {
+    key.rsplit('/').next().unwrap_or(key)
+}
+
+/// Orders a section's entries by their pages' weight and then title
+fn sort_key<'a>(key: &'a str, pages: &HashMap<&str, &'a Page>) -> (i32, &'a str) {
+    match pages.get(key) {
+        Some(page) => (page.front_matter.weight, page.front_matter.title.as_str()),
+        None => (0, segment(key)),
+    }
+}
@@ -0,0 +1,460 @@
+// src/page.rs
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{ Component, Path, PathBuf };
+use crate::config::{ Config, UrlStyle };
+use crate::generate;
+use crate::includes;
+use crate::summary;
+
+#[derive(Debug, Deserialize)]
+pub struct FrontMatter {
+    pub title: String,
+    pub slug: String,
+    pub date: Option<String>,
+    pub lastmod: Option<String>,
+    pub description: Option<String>,
+    pub keywords: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub author: Option<String>,
+    /// Share image for Open Graph and Twitter cards
+    pub image: Option<String>,
+    /// Keeps the page out of search engines (robots.txt, the sitemap and a
+    /// robots meta tag)
+    #[serde(default)]
+    pub noindex: bool,
+    pub sitemap: Option<SitemapOptions>,
+    /// Renders `$inline$` and `$$display$$` TeX math
+    #[serde(default)]
+    pub math: bool,
+    /// Orders the page among its siblings in the section tree and prev/next
+    /// links, lowest first
+    #[serde(default)]
+    pub weight: i32,
+    /// Menus listing the page, e.g. `menu: { main: { weight: 10 } }`
+    #[serde(default)]
+    pub menu: BTreeMap<String, MenuEntry>,
+    /// Old URLs of the page, redirected to it
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MenuEntry {
+    #[serde(default)]
+    pub weight: i32,
+    /// Menu text, instead of the page's title
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SitemapOptions {
+    pub priority: Option<f32>,
+    pub changefreq: Option<String>,
+    #[serde(default)]
+    pub exclude: bool,
+}
+
+#[derive(Debug)]
+pub struct Page {
+    /// Markdown source file, e.g. `routes/about.md`
+    pub path: PathBuf,
+    /// Site path the page is served at, e.g. `/about` (`/` for the index page)
+    pub url: String,
+    /// Language code of the page
+    pub lang: String,
+    /// Route path without its language, shared by all translations of a page
+    /// (`about` for both `routes/about.md` and `routes/about.de.md`)
+    pub key: String,
+    pub front_matter: FrontMatter,
+    /// Markdown body following the front matter
+    pub content: String,
+    /// Line of the source file the body starts on, for error messages
+    pub content_line: usize,
+    /// Number of words in the body, including included files
+    pub word_count: usize,
+    /// Estimated minutes to read the body
+    pub reading_time: usize,
+    /// Body of a page generated from a data file (see `generate`), which has
+    /// no markdown
+    pub html: Option<String>,
+}
+
+impl Page {
+    /// Bundle directory of the page (`routes/post/` for `routes/post/index.md`).
+    /// Other files in a bundle are published under the page's URL, so
+    /// `routes/post/photo.jpg` is served at `/post/photo.jpg`.
+    pub fn bundle_dir(&self) -> Option<&Path> {
+        bundle_dir(&self.path, &self.key)
+    }
+
+    /// Bundled file and URL for a relative link in the page (`photo.jpg` or
+    /// `./files/report.pdf#page=2`)
+    pub fn bundle_asset(&self, link: &str) -> Option<(PathBuf, String)> {
+        let dir = self.bundle_dir()?;
+        if link.starts_with(['/', '#', '?']) || link.contains(':') {
+            return None;
+        }
+
+        let (relative, suffix) = link.split_at(link.find(['#', '?']).unwrap_or(link.len()));
+        let relative = relative.strip_prefix("./").unwrap_or(relative);
+        let file = dir.join(relative);
+        if !is_bundled(dir, Path::new(relative)) || !file.is_file() {
+            return None;
+        }
+
+        Some((file, format!("{}/{}{}", self.url.trim_end_matches('/'), relative, suffix)))
+    }
+
+    /// Every file in the page's bundle, relative to the bundle directory
+    pub fn bundle_files(&self) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let mut files = Vec::new();
+        if let Some(dir) = self.bundle_dir() {
+            bundle_files_in(dir, dir, &mut files)?;
+        }
+
+        Ok(files)
+    }
+
+    /// HTML summary of the page for listings, see `summary::summary`
+    pub fn summary(&self, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+        summary::summary(self, config)
+    }
+
+    /// Home page URL for the page's language (`/` or `/de`, `/de/` for pretty
+    /// URLs)
+    pub fn home_url(&self) -> &str {
+        if self.key.is_empty() {
+            return &self.url;
+        }
+
+        let home = &self.url[..self.url.trim_end_matches('/').len() - self.key.len()];
+        if home == "/" || self.url.ends_with('/') { home } else { home.trim_end_matches('/') }
+    }
+}
+
+/// Splits a markdown file into its YAML front matter and body.
+///
+/// Returns `Ok(None)` when the file has no `---` delimited front matter.
+pub fn split_front_matter(
+    markdown_file: &str
+) -> Result<Option<(FrontMatter, &str)>, serde_yaml::Error> {
+    let split: Vec<&str> = markdown_file.splitn(3, "---").collect();
+    if split.len() != 3 {
+        return Ok(None);
+    }
+
+    let front_matter: FrontMatter = serde_yaml::from_str(split[1])?;
+    Ok(Some((front_matter, split[2])))
+}
+
+/// Where a markdown file in the routes directory is served, and in which language
+pub struct Route {
+    pub url: String,
+    pub lang: String,
+    pub key: String,
+}
+
+/// Maps a markdown file to its route.
+///
+/// Files in a language directory (`routes/de/about.md`) or with a language
+/// suffix (`routes/about.de.md`) are served under a language prefix (`/de/about`).
+/// Other files use the default language and are served without a prefix.
+pub fn route_for(path: &Path, config: &Config) -> Route {
+    let relative = path.strip_prefix("routes").unwrap_or(path).with_extension("");
+    let mut segments: Vec<String> = relative
+        .iter()
+        .map(|segment| segment.to_string_lossy().into_owned())
+        .collect();
+
+    let mut lang = None;
+    if segments.len() > 1 && config.is_language(&segments[0]) {
+        lang = Some(segments.remove(0));
+    }
+    if let Some(last) = segments.last_mut() {
+        if let Some((stem, suffix)) = last.rsplit_once('.') {
+            if config.is_language(suffix) {
+                lang = Some(suffix.to_owned());
+                *last = stem.to_owned();
+            }
+        }
+    }
+    if segments.last().map(String::as_str) == Some("index") {
+        segments.pop();
+    }
+
+    let key = segments.join("/");
+    let url = match (&lang, key.is_empty()) {
+        (Some(lang), true) => format!("/{}", lang),
+        (Some(lang), false) => format!("/{}/{}", lang, key),
+        (None, _) => format!("/{}", key),
+    };
+
+    Route {
+        url: canonical_url(&url, config),
+        lang: lang.unwrap_or_else(|| config.default_language.clone()),
+        key,
+    }
+}
+
+/// The canonical form of a site path in the configured URL style:
+/// `/about/`, `/about.html` and `/about/index` are all `/about`, or `/about/`
+/// with `url_style = "pretty"`. The home page is always `/`.
+pub fn canonical_url(url: &str, config: &Config) -> String {
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix(".html").unwrap_or(path);
+    let path = match path.strip_suffix("index") {
+        Some(parent) if parent.ends_with('/') => parent.trim_end_matches('/'),
+        _ => path,
+    };
+
+    match (path, config.build.url_style) {
+        ("", _) => "/".to_owned(),
+        (path, UrlStyle::Html) => path.to_owned(),
+        (path, UrlStyle::Pretty) => format!("{}/", path),
+    }
+}
+
+/// A page is a bundle when it is the `index.md` (or `index.<lang>.md`) of a
+/// directory other than the routes directory or a language directory
+fn bundle_dir<'a>(path: &'a Path, key: &str) -> Option<&'a Path> {
+    let file_name = path.file_name()?.to_str()?;
+    if key.is_empty() || !file_name.starts_with("index.") {
+        return None;
+    }
+
+    path.parent()
+}
+
+/// Whether `relative` is a file of the bundle in `dir`: markdown pages and
+/// files of bundles nested inside it are not
+fn is_bundled(dir: &Path, relative: &Path) -> bool {
+    if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+        return false;
+    }
+    if relative.extension() == Some("md".as_ref()) {
+        return false;
+    }
+
+    let mut parent = dir.to_path_buf();
+    let mut segments: Vec<_> = relative.iter().collect();
+    segments.pop();
+    for segment in segments {
+        parent.push(segment);
+        if parent.join("index.md").is_file() {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn bundle_files_in(
+    dir: &Path,
+    bundle: &Path,
+    files: &mut Vec<PathBuf>
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(bundle)?;
+        if path.is_dir() {
+            if !path.join("index.md").is_file() {
+                bundle_files_in(&path, bundle, files)?;
+            }
+        } else if is_bundled(bundle, relative) {
+            files.push(relative.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the bundled file served at a site path such as `/post/photo.jpg`
+pub fn find_bundle_asset(url: &str, config: &Config) -> Option<PathBuf> {
+    let mut split = url.len();
+    while let Some(slash) = url[..split].rfind('/') {
+        split = slash;
+        let (page_url, relative) = (&url[..slash], &url[slash + 1..]);
+        let Some(source) = find_source(&canonical_url(page_url, config), config) else {
+            continue;
+        };
+        let route = route_for(&source, config);
+        let dir = bundle_dir(&source, &route.key)?;
+
+        let file = dir.join(relative);
+        return (is_bundled(dir, Path::new(relative)) && file.is_file()).then_some(file);
+    }
+
+    None
+}
+
+/// Markdown files in `dir` that could hold the route `key`, optionally with a
+/// language suffix
+fn source_candidates(dir: &Path, key: &str, suffix: Option<&str>) -> Vec<PathBuf> {
+    let file_name = |stem: &str| match suffix {
+        Some(suffix) => format!("{}.{}.md", stem, suffix),
+        None => format!("{}.md", stem),
+    };
+
+    if key.is_empty() {
+        vec![dir.join(file_name("index"))]
+    } else {
+        vec![dir.join(file_name(key)), dir.join(key).join(file_name("index"))]
+    }
+}
+
+/// Finds the markdown file served at a site path such as `/de/about`
+pub fn find_source(url: &str, config: &Config) -> Option<PathBuf> {
+    let key = url.trim_matches('/');
+    if Path::new(key).components().any(|component| !matches!(component, Component::Normal(_))) {
+        return None;
+    }
+
+    let routes = Path::new("routes");
+    let mut candidates = source_candidates(routes, key, None);
+    let (first, rest) = key.split_once('/').unwrap_or((key, ""));
+    if config.is_language(first) {
+        candidates.extend(source_candidates(routes, rest, Some(first)));
+    }
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file() && route_for(candidate, config).url == url)
+}
+
+/// URLs of every translation of a page (including itself), keyed by language
+pub fn translations(page: &Page, config: &Config) -> Vec<(String, String)> {
+    let routes = Path::new("routes");
+    let mut translations = Vec::new();
+
+    for lang in config.languages.keys() {
+        let mut candidates = source_candidates(&routes.join(lang), &page.key, None);
+        candidates.extend(source_candidates(routes, &page.key, Some(lang)));
+        if *lang == config.default_language {
+            candidates.extend(source_candidates(routes, &page.key, None));
+        }
+
+        let route = candidates
+            .iter()
+            .filter(|candidate| candidate.is_file())
+            .map(|candidate| route_for(candidate, config))
+            .find(|route| route.lang == *lang && route.key == page.key);
+        if let Some(route) = route {
+            translations.push((route.lang, route.url));
+        }
+    }
+
+    translations
+}
+
+pub fn load_page(path: &Path, config: &Config) -> Result<Option<Page>, Box<dyn std::error::Error>> {
+    let markdown_file = fs::read_to_string(path)?;
+    let mut page = split_front_matter(&markdown_file)?.map(|(front_matter, content)| {
+        let route = route_for(path, config);
+        Page {
+            path: path.to_path_buf(),
+            url: route.url,
+            lang: route.lang,
+            key: route.key,
+            front_matter,
+            content: content.to_owned(),
+            content_line: markdown_file[..markdown_file.len() - content.len()].matches('\n').count() + 1,
+            word_count: 0,
+            reading_time: 0,
+            html: None,
+        }
+    });
+
+    // Count included text too, falling back to the page's own text when an
+    // include is broken (rendering reports those)
+    if let Some(page) = &mut page {
+        let content = includes::expand(page).unwrap_or_else(|_| page.content.clone());
+        page.word_count = summary::word_count(&content);
+        page.reading_time = summary::reading_time(page.word_count, config.summary.words_per_minute);
+    }
+
+    Ok(page)
+}
+
+/// Loads every markdown page in the routes directory and its subdirectories,
+/// and the pages generated from data files, sorted by URL
+pub fn load_pages(config: &Config) -> Result<Vec<Page>, Box<dyn std::error::Error>> {
+    let mut pages = Vec::new();
+    load_pages_in(Path::new("routes"), config, &mut pages)?;
+    pages.extend(generate::generated_pages(config)?);
+
+    pages.sort_by(|a, b| a.url.cmp(&b.url));
+    if let Some(pair) = pages.windows(2).find(|pair| pair[0].url == pair[1].url) {
+        return Err(
+            format!(
+                "{} and {} are both served at {}",
+                pair[0].path.display(),
+                pair[1].path.display(),
+                pair[0].url
+            ).into()
+        );
+    }
+
+    Ok(pages)
+}
+
+fn load_pages_in(
+    dir: &Path,
+    config: &Config,
+    pages: &mut Vec<Page>
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            load_pages_in(&path, config, pages)?;
+        } else if path.is_file() && path.extension() == Some("md".as_ref()) {
+            if let Some(page) = load_page(&path, config)? {
+                pages.push(page);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BuildConfig;
+
+    fn config(url_style: UrlStyle) -> Config {
+        Config { build: BuildConfig { url_style, ..BuildConfig::default() }, ..Config::default() }
+    }
+
+    #[test]
+    fn canonical_url_drops_suffixes_for_html_urls() {
+        let config = config(UrlStyle::Html);
+        for url in ["/about", "/about/", "/about.html", "/about/index", "/about/index.html"] {
+            assert_eq!(canonical_url(url, &config), "/about");
+        }
+        assert_eq!(canonical_url("/de/docs/", &config), "/de/docs");
+        assert_eq!(canonical_url("/reindex", &config), "/reindex");
+    }
+
+    #[test]
+    fn canonical_url_adds_a_trailing_slash_for_pretty_urls() {
+        let config = config(UrlStyle::Pretty);
+        for url in ["/about", "/about/", "/about.html", "/about/index", "/about/index.html"] {
+            assert_eq!(canonical_url(url, &config), "/about/");
+        }
+        assert_eq!(canonical_url("/reindex", &config), "/reindex/");
+    }
+
+    #[test]
+    fn canonical_url_keeps_the_home_page_at_the_root() {
+        for url_style in [UrlStyle::Html, UrlStyle::Pretty] {
+            let config = config(url_style);
+            for url in ["/", "/index", "/index.html", ""] {
+                assert_eq!(canonical_url(url, &config), "/");
+            }
+        }
+    }
+}
@@ -1,42 +1,107 @@
 // src/page_handler.rs
 
-use actix_web::{ web, HttpResponse, Responder };
-use pulldown_cmark::{ html, Options, Parser };
+use actix_files::NamedFile;
+use actix_web::http::header;
+use actix_web::{ web, HttpRequest, HttpResponse };
 use std::fs;
-use std::path::PathBuf;
-use crate::root_handler::FrontMatter;
-
-pub async fn page(path: web::Path<PathBuf>) -> impl Responder {
-    let page_path = format!("routes/{}.md", path.as_path().to_str().unwrap());
-    if let Ok(markdown_file) = fs::read_to_string(&page_path) {
-        let split: Vec<&str> = markdown_file.splitn(3, "---").collect();
-        if split.len() == 3 {
-            let front_matter: FrontMatter = serde_yaml::from_str(split[1]).unwrap();
-            // println!("{:?}", front_matter);
-
-            let mut options = Options::empty();
-            options.insert(Options::ENABLE_STRIKETHROUGH);
-            let parser = Parser::new_ext(split[2], options);
-            let mut html_output = String::new();
-            html::push_html(&mut html_output, parser);
-
-            // Load template
-            let template = fs::read_to_string("template.html").unwrap();
-
-            // Replace placeholders in template
-            let html_output = template
-                .replace("{{title}}", &front_matter.title)
-                .replace("{{slug}}", &front_matter.slug)
-                .replace("{{description}}", front_matter.description.as_deref().unwrap_or(""))
-                .replace("{{keywords}}", front_matter.keywords.as_deref().unwrap_or(""))
-                .replace("{{author}}", front_matter.author.as_deref().unwrap_or(""))
-                .replace("{{content}}", &html_output);
-
-            HttpResponse::Ok().body(html_output)
-        } else {
-            HttpResponse::NotFound().body(format!("Invalid Markdown file format: {}", &page_path))
+use crate::asset_handler::AssetManifest;
+use crate::config::Config;
+use crate::generate;
+use crate::page::{ self, Page };
+use crate::redirects_handler::Redirects;
+use crate::render;
+
+/// Serves the page at a path, a file from a page bundle, or a redirect.
+/// Other forms of a page's URL (`/about/`, `/about.html` or `/about/index`
+/// for `/about`) redirect to its canonical URL.
+pub async fn page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    config: web::Data<Config>,
+    assets: web::Data<AssetManifest>,
+    redirects: web::Data<Redirects>
+) -> actix_web::Result<HttpResponse> {
+    let requested = format!("/{}", path.into_inner());
+    let url = page::canonical_url(&requested, &config);
+    if let Some(response) = redirects.response(&url) {
+        return Ok(response);
+    }
+    if page::find_source(&url, &config).is_none() {
+        if let Some(file) = page::find_bundle_asset(&requested, &config) {
+            return Ok(NamedFile::open(file)?.into_response(&req));
+        }
+    }
+
+    if url != requested && is_page(&url, &config) {
+        let location = match req.query_string() {
+            "" => url,
+            query => format!("{}?{}", url, query),
+        };
+        return Ok(HttpResponse::MovedPermanently().insert_header((header::LOCATION, location)).finish());
+    }
+
+    Ok(page_response(&url, &config, &assets))
+}
+
+/// Whether a page, from markdown or a data file, is served at a canonical URL
+fn is_page(url: &str, config: &Config) -> bool {
+    page::find_source(url, config).is_some() ||
+        generate::generated_pages(config).is_ok_and(|pages| pages.iter().any(|page| page.url == url))
+}
+
+/// Renders the page served at `url`, from markdown or a data file, into the
+/// live template
+pub fn page_response(url: &str, config: &Config, assets: &AssetManifest) -> HttpResponse {
+    // Load every page for menus and other navigation
+    let pages = match page::load_pages(config) {
+        Ok(pages) => pages,
+        Err(e) => {
+            eprintln!("Failed to load pages: {:?}", e);
+            return HttpResponse::InternalServerError().body(format!("Failed to load page: {}", url));
+        }
+    };
+
+    let page = match page::find_source(url, config) {
+        Some(page_path) => {
+            match page::load_page(&page_path, config) {
+                Ok(Some(page)) => page,
+                Ok(None) => {
+                    return HttpResponse::NotFound().body(
+                        format!("Invalid Markdown file format: {}", page_path.display())
+                    );
+                }
+                Err(e) => {
+                    eprintln!("Failed to load {}: {:?}", page_path.display(), e);
+                    return HttpResponse::InternalServerError().body(format!("Failed to load page: {}", url));
+                }
+            }
+        }
+        // Pages generated from data files have no markdown source
+        None => {
+            return match pages.iter().find(|page| page.html.is_some() && page.url == url) {
+                Some(page) => render_response(page, config, assets, &pages),
+                None => HttpResponse::NotFound().body(format!("Page not found: {}", url)),
+            };
+        }
+    };
+
+    render_response(&page, config, assets, &pages)
+}
+
+fn render_response(page: &Page, config: &Config, assets: &AssetManifest, pages: &[Page]) -> HttpResponse {
+    // Load template
+    let template = fs::read_to_string("template.html").unwrap();
+
+    let mut html_output = match render::render_page(page, &template, config, assets, pages) {
+        Ok(html_output) => html_output,
+        Err(e) => {
+            eprintln!("Failed to render {}: {}", page.path.display(), e);
+            return HttpResponse::InternalServerError().body(format!("Failed to render page: {}", page.url));
         }
-    } else {
-        HttpResponse::NotFound().body(format!("Page not found: {}", &page_path))
+    };
+    if config.build.minify_html {
+        html_output = render::minify_html(&html_output);
     }
+
+    HttpResponse::Ok().body(html_output)
 }
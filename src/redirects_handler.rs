@@ -0,0 +1,190 @@
+// src/redirects_handler.rs
+
+use actix_web::http::{ header, StatusCode };
+use actix_web::HttpResponse;
+use std::fmt::Write;
+use crate::config::Config;
+use crate::page;
+use crate::render::escape;
+
+const DEFAULT_STATUS: u16 = 301;
+
+/// A redirect from the config or a page's `aliases`
+#[derive(Debug)]
+pub struct Redirect {
+    /// Old URL, or a prefix when `splat` is set
+    pub from: String,
+    pub to: String,
+    pub status: u16,
+    /// Whether `from` ended in `/*`, matching every URL under it
+    pub splat: bool,
+}
+
+/// Every redirect of the site, built once at startup
+pub struct Redirects {
+    redirects: Vec<Redirect>,
+}
+
+impl Redirects {
+    /// Where a URL redirects to, with the status to redirect with
+    pub fn find(&self, url: &str) -> Option<(StatusCode, String)> {
+        self.redirects.iter().find_map(|redirect| {
+            let to = if redirect.splat {
+                let rest = url.strip_prefix(&redirect.from)?;
+                if !rest.is_empty() && !rest.starts_with('/') && !redirect.from.ends_with('/') {
+                    return None;
+                }
+                redirect.to.replace(":splat", rest.trim_start_matches('/'))
+            } else if url == redirect.from {
+                redirect.to.clone()
+            } else {
+                return None;
+            };
+
+            Some((StatusCode::from_u16(redirect.status).ok()?, to))
+        })
+    }
+
+    /// Redirect response for a URL, if it is redirected
+    pub fn response(&self, url: &str) -> Option<HttpResponse> {
+        let (status, to) = self.find(url)?;
+        Some(HttpResponse::build(status).insert_header((header::LOCATION, to)).finish())
+    }
+
+    /// The redirects in the `_redirects` format of static hosts such as
+    /// Netlify and Cloudflare Pages. Exact redirects are forced (`301!`):
+    /// their redirect pages (see `stubs`) are written too, and hosts skip
+    /// rules that aren't forced when a file exists at the URL.
+    pub fn redirects_file(&self) -> String {
+        let mut file = String::new();
+        for redirect in &self.redirects {
+            let (from, force) = if redirect.splat {
+                (format!("{}*", redirect.from), "")
+            } else {
+                (redirect.from.clone(), "!")
+            };
+            writeln!(file, "{} {} {}{}", from, redirect.to, redirect.status, force).unwrap();
+        }
+
+        file
+    }
+
+    /// HTML pages for hosts without `_redirects` support, by URL. Prefix
+    /// redirects have none.
+    pub fn stubs(&self, config: &Config) -> Vec<(&str, String)> {
+        self.redirects
+            .iter()
+            .filter(|redirect| !redirect.splat)
+            .map(|redirect| (redirect.from.as_str(), redirect_stub(&redirect.to, config)))
+            .collect()
+    }
+}
+
+/// Collects the redirects in the config and the `aliases` of every page.
+/// Redirecting from a URL that has a page, or redirecting the same URL twice,
+/// is an error.
+pub fn build_redirects(config: &Config) -> Result<Redirects, Box<dyn std::error::Error>> {
+    let pages = page::load_pages(config)?;
+    let mut redirects = Vec::new();
+
+    for redirect in &config.redirects {
+        let status = redirect.status.unwrap_or(DEFAULT_STATUS);
+        if !matches!(status, 301 | 302 | 307 | 308) {
+            return Err(format!("redirect from {}: unsupported status {}", redirect.from, status).into());
+        }
+
+        let (from, splat) = match redirect.from.strip_suffix('*') {
+            Some(prefix) => (prefix.to_owned(), true),
+            None => (page::canonical_url(&redirect.from, config), false),
+        };
+        redirects.push(Redirect { from, to: redirect.to.clone(), status, splat });
+    }
+    for page in &pages {
+        for alias in &page.front_matter.aliases {
+            redirects.push(Redirect {
+                from: page::canonical_url(alias, config),
+                to: page.url.clone(),
+                status: DEFAULT_STATUS,
+                splat: false,
+            });
+        }
+    }
+
+    for (i, redirect) in redirects.iter().enumerate() {
+        if !redirect.from.starts_with('/') {
+            return Err(format!("redirect from {}: URLs must start with /", redirect.from).into());
+        }
+        if let Some(page) = pages.iter().find(|page| !redirect.splat && page.url == redirect.from) {
+            return Err(format!("{} redirects to {}, but {} is served there", redirect.from, redirect.to, page.path.display()).into());
+        }
+        if redirects[..i].iter().any(|other| other.from == redirect.from && other.splat == redirect.splat) {
+            return Err(format!("{} is redirected more than once", redirect.from).into());
+        }
+    }
+
+    Ok(Redirects { redirects })
+}
+
+/// Page that sends browsers on to `to`, and tells search engines it moved
+fn redirect_stub(to: &str, config: &Config) -> String {
+    let absolute = if to.starts_with('/') { config.absolute_url(to) } else { to.to_owned() };
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8" />
+    <title>Redirecting to {to}</title>
+    <link rel="canonical" href="{absolute}" />
+    <meta name="robots" content="noindex" />
+    <meta http-equiv="refresh" content="0; url={to}" />
+  </head>
+  <body>
+    <a href="{to}">Redirecting to {to}</a>
+  </body>
+</html>
+"#,
+        to = escape(to),
+        absolute = escape(&absolute)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(from: &str, to: &str, status: u16, splat: bool) -> Redirect {
+        Redirect { from: from.to_owned(), to: to.to_owned(), status, splat }
+    }
+
+    fn redirects() -> Redirects {
+        Redirects {
+            redirects: vec![
+                redirect("/old", "/new", 301, false),
+                redirect("/blog", "/posts/:splat", 302, true),
+                redirect("/docs/", "/guide/:splat", 301, true),
+            ],
+        }
+    }
+
+    #[test]
+    fn find_matches_exact_urls() {
+        let redirects = redirects();
+        assert_eq!(redirects.find("/old"), Some((StatusCode::MOVED_PERMANENTLY, "/new".to_owned())));
+        assert_eq!(redirects.find("/old/"), None);
+        assert_eq!(redirects.find("/older"), None);
+    }
+
+    #[test]
+    fn find_matches_splats_at_segment_boundaries() {
+        let redirects = redirects();
+        assert_eq!(redirects.find("/blog/a/b"), Some((StatusCode::FOUND, "/posts/a/b".to_owned())));
+        assert_eq!(redirects.find("/blog"), Some((StatusCode::FOUND, "/posts/".to_owned())));
+        assert_eq!(redirects.find("/blogroll"), None);
+        assert_eq!(redirects.find("/docs/install"), Some((StatusCode::MOVED_PERMANENTLY, "/guide/install".to_owned())));
+    }
+
+    #[test]
+    fn redirects_file_forces_exact_rules() {
+        assert_eq!(redirects().redirects_file(), "/old /new 301!\n/blog* /posts/:splat 302\n/docs/* /guide/:splat 301\n");
+    }
+}
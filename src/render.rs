@@ -0,0 +1,420 @@
+// src/render.rs
+
+use pulldown_cmark::{ html, CodeBlockKind, Event, Options, Parser, Tag };
+use pulldown_cmark::escape::escape_html;
+use slug::slugify;
+use std::collections::HashMap;
+use std::path::{ Component, Path, PathBuf };
+use crate::admonitions;
+use crate::asset_handler::AssetManifest;
+use crate::config::Config;
+use crate::data;
+use crate::diagrams;
+use crate::highlight;
+use crate::icons;
+use crate::images;
+use crate::includes::{ self, SourceMap };
+use crate::math;
+use crate::navigation;
+use crate::page::{ self, Page };
+use crate::seo;
+use crate::shortcodes;
+
+/// Converts a page's markdown body to HTML.
+///
+/// Includes, math (on pages with `math: true`), shortcodes and admonitions are
+/// expanded first (see `includes::expand`, `math::expand`, `shortcodes::expand`
+/// and `admonitions::expand`).
+/// Headings get `id`s from their text so they can be linked to, and
+/// `@/guides/install.md#requirements` links are resolved to the URL of the
+/// page in `routes/guides/install.md`. Linking to a missing page or heading is
+/// an error. Fenced ```` ```dot ```` blocks become inline SVG diagrams, and other
+/// fenced blocks are highlighted for their language (see `highlight::code_block_html`).
+///
+/// Pages generated from data files are already HTML.
+pub fn render_markdown(page: &Page, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(html) = &page.html {
+        return Ok(html.clone());
+    }
+    let (markdown, sources) = includes::expand_with_sources(page)?;
+    render_fragment(&markdown, &sources, page, config)
+}
+
+/// Converts a piece of a page's markdown, such as a shortcode body, to HTML.
+/// `sources` maps the piece to the files and lines it was written on, for
+/// error messages.
+pub fn render_fragment(
+    markdown: &str,
+    sources: &SourceMap,
+    page: &Page,
+    config: &Config
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    // Resolve `@/` links before math and shortcodes are swapped for
+    // placeholders, so broken ones are reported where they were written
+    let mut internal_links = HashMap::new();
+    for (event, range) in Parser::new_ext(markdown, options).into_offset_iter() {
+        if let Event::Start(Tag::Link(_, dest, _)) = event {
+            if let Some(target) = dest.strip_prefix("@/") {
+                let url = internal_link(target, config).map_err(|e| {
+                    format!("{}: broken link @/{}: {}", sources.position(markdown, range.start), target, e)
+                })?;
+                internal_links.insert(target.to_owned(), url);
+            }
+        }
+    }
+
+    let (markdown, sources, formulas) = if page.front_matter.math {
+        math::expand(markdown, sources, config)?
+    } else {
+        (markdown.to_owned(), sources.clone(), Vec::new())
+    };
+    let (markdown, shortcodes) = shortcodes::expand(&markdown, &sources, page, config)?;
+    let markdown = admonitions::expand(&markdown, config);
+
+    let parser = Parser::new_ext(&markdown, options);
+
+    // Replace images with responsive markup, using their text as the alt text.
+    // Relative links and images in a page bundle point at the bundled files.
+    let mut events = Vec::new();
+    let mut image: Option<(String, String, String)> = None;
+    let mut heading: Option<(usize, u32, String)> = None;
+    let mut heading_ids = HeadingIds::default();
+    let mut code_block: Option<(String, String)> = None;
+    for event in parser {
+        // Fenced ```dot blocks become inline SVG diagrams, and other fenced
+        // blocks with a language are highlighted
+        if let Some((lang, source)) = &mut code_block {
+            match event {
+                Event::Text(text) => source.push_str(&text),
+                Event::End(Tag::CodeBlock(_)) => {
+                    if diagrams::is_diagram(lang) {
+                        events.push(Event::Html(diagrams::diagram_html(lang, source, page).into()));
+                    } else {
+                        match highlight::code_block_html(source, lang, &config.highlight) {
+                            Ok(Some(html)) => events.push(Event::Html(html.into())),
+                            Ok(None) => {
+                                let kind = CodeBlockKind::Fenced(lang.clone().into());
+                                events.push(Event::Start(Tag::CodeBlock(kind.clone())));
+                                events.push(Event::Text(source.clone().into()));
+                                events.push(Event::End(Tag::CodeBlock(kind)));
+                            }
+                            Err(e) => return Err(format!("{}: {}", page.path.display(), e).into()),
+                        }
+                    }
+                    code_block = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) = &event {
+            if !lang.is_empty() {
+                code_block = Some((lang.to_string(), String::new()));
+                continue;
+            }
+        }
+
+        if let (Some((_, _, text)), Event::Text(t) | Event::Code(t)) = (&mut heading, &event) {
+            text.push_str(t);
+        }
+
+        match (event, &mut image) {
+            (Event::Start(Tag::Heading(level)), None) => {
+                // Placeholder for the opening tag, which needs the heading's text
+                heading = Some((events.len(), level, String::new()));
+                events.push(Event::Html("".into()));
+            }
+            (Event::End(Tag::Heading(level)), None) => {
+                if let Some((index, _, text)) = heading.take() {
+                    let id = heading_ids.id(&text);
+                    events[index] = Event::Html(format!(r#"<h{} id="{}">"#, level, escape(&id)).into());
+                }
+                events.push(Event::End(Tag::Heading(level)));
+            }
+            (Event::Start(Tag::Link(link_type, dest, title)), None) => {
+                let dest = match (dest.strip_prefix("@/"), page.bundle_asset(&dest)) {
+                    (Some(target), _) => {
+                        match internal_links.get(target) {
+                            Some(url) => url.clone().into(),
+                            // Only parsed as a link once math and shortcodes are swapped out
+                            None => {
+                                internal_link(target, config).map_err(|e| {
+                                    format!("{}: broken link @/{}: {}", page.path.display(), target, e)
+                                })?.into()
+                            }
+                        }
+                    }
+                    (None, Some((_, url))) => url.into(),
+                    (None, None) => dest,
+                };
+                events.push(Event::Start(Tag::Link(link_type, dest, title)));
+            }
+            (Event::Start(Tag::Image(_, src, title)), None) => {
+                image = Some((src.to_string(), title.to_string(), String::new()));
+            }
+            (Event::End(Tag::Image(..)), Some(_)) => {
+                let (src, title, alt) = image.take().unwrap();
+                let html = match page.bundle_asset(&src) {
+                    Some((path, url)) => images::image_html(&url, Some(&path), &alt, &title, &config.images),
+                    None => images::image_html(&src, None, &alt, &title, &config.images),
+                };
+                events.push(Event::Html(html.into()));
+            }
+            (Event::Text(text) | Event::Code(text), Some((_, _, alt))) => alt.push_str(&text),
+            (_, Some(_)) => {}
+            (event, None) => events.push(event),
+        }
+    }
+
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, events.into_iter());
+
+    Ok(math::insert(&shortcodes::insert(&html_output, &shortcodes), &formulas))
+}
+
+/// Gives headings unique ids made from their text: `Getting started` becomes
+/// `getting-started`, and a second heading with that text `getting-started-1`
+#[derive(Default)]
+struct HeadingIds {
+    seen: HashMap<String, usize>,
+}
+
+impl HeadingIds {
+    fn id(&mut self, text: &str) -> String {
+        let mut id = slugify(text);
+        if id.is_empty() {
+            id = "section".to_owned();
+        }
+
+        let count = self.seen.entry(id.clone()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            format!("{}-{}", id, *count - 1)
+        } else {
+            id
+        }
+    }
+}
+
+/// Ids of the headings in a markdown body, as `render_markdown` assigns them
+pub fn heading_ids(markdown: &str) -> Vec<String> {
+    let mut heading_ids = HeadingIds::default();
+    let mut ids = Vec::new();
+    let mut heading: Option<String> = None;
+
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        match (event, &mut heading) {
+            (Event::Start(Tag::Heading(_)), _) => heading = Some(String::new()),
+            (Event::End(Tag::Heading(_)), Some(text)) => {
+                ids.push(heading_ids.id(text));
+                heading = None;
+            }
+            (Event::Text(t) | Event::Code(t), Some(text)) => text.push_str(&t),
+            _ => {}
+        }
+    }
+
+    ids
+}
+
+/// Source files of the pages a markdown body links to with `@/` links
+pub fn internal_link_targets(markdown: &str) -> Vec<PathBuf> {
+    Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link(_, dest, _)) => {
+                let target = dest.strip_prefix("@/")?;
+                let file = target.split('#').next().unwrap_or(target);
+                Some(Path::new("routes").join(file))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// URL of the page in `routes/<target>`, where the target is a markdown file
+/// path with an optional `#heading` anchor
+fn internal_link(target: &str, config: &Config) -> Result<String, String> {
+    let (file, anchor) = match target.split_once('#') {
+        Some((file, anchor)) => (file, Some(anchor)),
+        None => (target, None),
+    };
+
+    let path = Path::new("routes").join(file);
+    let is_normal = Path::new(file).components().all(|component| matches!(component, Component::Normal(_)));
+    if !is_normal || !path.is_file() {
+        return Err(format!("no page at {}", path.display()));
+    }
+
+    let page = match page::load_page(&path, config) {
+        Ok(Some(page)) => page,
+        Ok(None) => {
+            return Err(format!("invalid Markdown file format: {}", path.display()));
+        }
+        Err(e) => {
+            return Err(format!("failed to load {}: {}", path.display(), e));
+        }
+    };
+
+    let content = includes::expand(&page).map_err(|e| e.to_string())?;
+    match anchor {
+        Some(anchor) if !heading_ids(&content).iter().any(|id| id == anchor) => {
+            Err(format!("no #{} heading in {}", anchor, path.display()))
+        }
+        Some(anchor) => Ok(format!("{}#{}", page.url, anchor)),
+        None => Ok(page.url),
+    }
+}
+
+/// Renders a page into the given template, replacing its `{{...}}` placeholders.
+/// `pages` are all the site's pages, for menus and other navigation.
+pub fn render_page(
+    page: &Page,
+    template: &str,
+    config: &Config,
+    assets: &AssetManifest,
+    pages: &[Page]
+) -> Result<String, Box<dyn std::error::Error>> {
+    let front_matter = &page.front_matter;
+    let html_output = render_markdown(page, config)?;
+
+    // `{{data.team.name}}` and `{{#each data.team.members}}` use the data files
+    let template = data::render(template, "template.html")?;
+    // `{{t.key}}` is replaced with a string from the page language's string table
+    let template = replace_helper(&template, "{{t.", "}}", |key| {
+        escape(config.translate(&page.lang, key))
+    });
+    // `{{asset("styles.css")}}` is replaced with the fingerprinted asset URL
+    let template = replace_helper(&template, "{{asset(\"", "\")}}", |path| {
+        escape(&assets.url(path))
+    });
+
+    // `{{menu.main}}` is replaced with the items of the `main` menu
+    let template = replace_helper(&template, "{{menu.", "}}", |name| {
+        navigation::menu_html(name, page, pages, config)
+    });
+
+    // Only render the summary and section tree for templates that show them
+    let summary = if template.contains("{{summary}}") { page.summary(config)? } else { String::new() };
+    let sections = if template.contains("{{sections}}") {
+        navigation::sections_html(page, pages)
+    } else {
+        String::new()
+    };
+
+    Ok(
+        template
+            .replace("{{lang}}", &page.lang)
+            .replace("{{title}}", &escape(&front_matter.title))
+            .replace("{{slug}}", &escape(&front_matter.slug))
+            .replace("{{description}}", &escape(front_matter.description.as_deref().unwrap_or("")))
+            .replace("{{keywords}}", &escape(front_matter.keywords.as_deref().unwrap_or("")))
+            .replace("{{author}}", &escape(front_matter.author.as_deref().unwrap_or("")))
+            .replace("{{word_count}}", &page.word_count.to_string())
+            .replace("{{reading_time}}", &page.reading_time.to_string())
+            .replace("{{summary}}", &summary)
+            .replace("{{sections}}", &sections)
+            .replace("{{breadcrumbs}}", &navigation::breadcrumbs_html(page, pages, config))
+            .replace("{{prev_next}}", &navigation::prev_next_html(page, pages))
+            .replace("{{seo}}", &seo::seo_tags(page, config))
+            .replace("{{critical_css}}", &critical_css(assets))
+            .replace("{{favicons}}", &icons::favicon_tags(config, assets))
+            .replace("{{content}}", &html_output)
+    )
+}
+
+/// Inline `<style>` element for the critical stylesheet, if one is configured
+fn critical_css(assets: &AssetManifest) -> String {
+    match assets.critical_css() {
+        // Keep "</style>" inside the stylesheet from closing the element
+        Some(css) => format!("<style>{}</style>", css.replace("</", "<\\/")),
+        None => String::new(),
+    }
+}
+
+/// Replaces every `<open>argument<close>` placeholder with `helper(argument)`
+pub fn replace_helper(
+    template: &str,
+    open: &str,
+    close: &str,
+    helper: impl Fn(&str) -> String
+) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(open) {
+        output.push_str(&rest[..start]);
+        let argument = &rest[start + open.len()..];
+        match argument.find(close) {
+            Some(end) => {
+                output.push_str(&helper(&argument[..end]));
+                rest = &argument[end + close.len()..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Minifies a rendered page: collapses whitespace outside `<pre>` and
+/// `<textarea>`, strips comments and drops attribute quotes where that is
+/// safe. Inline scripts and styles are left untouched.
+pub fn minify_html(html: &str) -> String {
+    let mut cfg = minify_html::Cfg::new();
+    cfg.do_not_minify_doctype = true;
+    cfg.keep_closing_tags = true;
+    cfg.keep_html_and_head_opening_tags = true;
+    cfg.ensure_spec_compliant_unquoted_attribute_values = true;
+
+    String::from_utf8_lossy(&minify_html::minify(html.as_bytes(), &cfg)).into_owned()
+}
+
+/// Escapes text for use in HTML content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, text).unwrap();
+    escaped
+}
+
+/// Decodes the characters `escape` escapes
+pub fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heading_ids_are_unique_slugs() {
+        let markdown = "# Getting `started`\n\n## Setup\n\n## Setup\n\n### !!!\n";
+        assert_eq!(heading_ids(markdown), ["getting-started", "setup", "setup-1", "section"]);
+    }
+
+    #[test]
+    fn internal_link_targets_are_route_files() {
+        let markdown = "See [install](@/guides/install.md#requirements), [home](/) and [faq](@/faq.md).";
+        assert_eq!(
+            internal_link_targets(markdown),
+            [Path::new("routes/guides/install.md"), Path::new("routes/faq.md")]
+        );
+    }
+
+    #[test]
+    fn internal_link_rejects_missing_and_escaping_paths() {
+        let config = Config::default();
+        assert_eq!(internal_link("no/such/page.md", &config), Err("no page at routes/no/such/page.md".to_owned()));
+        assert!(internal_link("../Cargo.toml", &config).is_err());
+    }
+}
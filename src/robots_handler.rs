@@ -1,22 +1,91 @@
 // src/robots_handler.rs
 
-use actix_web::{ HttpResponse };
+use actix_web::{ web, HttpResponse };
+use std::fs;
 use std::io::Cursor;
 use std::io::Write;
+use crate::config::{ Config, RobotsRule };
+use crate::page;
+
+/// Rendered robots.txt, built once at startup
+pub struct Robots {
+    body: String,
+}
+
+impl Robots {
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+/// Builds robots.txt from the `[robots]` section of the config.
+///
+/// Pages with `noindex: true` in their front matter are disallowed for every
+/// user agent, with a `$` anchor so that only the page itself is matched and
+/// not every URL starting with its path. When `robots.file` is set, that file
+/// is used verbatim instead.
+pub fn build_robots(config: &Config) -> Result<Robots, Box<dyn std::error::Error>> {
+    if let Some(file) = &config.robots.file {
+        return Ok(Robots { body: fs::read_to_string(file)? });
+    }
+
+    let mut rules = config.robots.rules.clone();
+    if rules.is_empty() {
+        rules.push(RobotsRule {
+            user_agent: "*".to_owned(),
+            allow: vec!["/".to_owned()],
+            disallow: Vec::new(),
+            crawl_delay: None,
+        });
+    }
+
+    let noindex: Vec<String> = page::load_pages(config)?
+        .into_iter()
+        .filter(|page| page.front_matter.noindex)
+        .map(|page| disallow_path(&page.url))
+        .collect();
 
-pub async fn robots() -> Result<HttpResponse, actix_web::Error> {
     let mut buffer = Cursor::new(Vec::<u8>::new());
 
-    writeln!(buffer, "User-Agent: *")?;
-    writeln!(buffer, "Allow: /")?;
-    writeln!(buffer, "Sitemap: http://127.0.0.1:8080/sitemap.xml")?;
+    for rule in &rules {
+        writeln!(buffer, "User-Agent: {}", rule.user_agent)?;
+        for path in &rule.allow {
+            writeln!(buffer, "Allow: {}", path)?;
+        }
+        for path in rule.disallow.iter().chain(&noindex) {
+            writeln!(buffer, "Disallow: {}", path)?;
+        }
+        if let Some(crawl_delay) = rule.crawl_delay {
+            writeln!(buffer, "Crawl-delay: {}", crawl_delay)?;
+        }
+        writeln!(buffer)?;
+    }
+
+    writeln!(buffer, "Sitemap: {}", config.absolute_url("/sitemap.xml"))?;
+
+    Ok(Robots { body: String::from_utf8(buffer.into_inner())? })
+}
+
+/// Disallow path matching exactly a page URL: `/secret$`, so that `/secrets`
+/// stays allowed. Pretty URLs (`/secret/`) are left as they are, which also
+/// covers the files of the page's bundle, but the home page is anchored.
+fn disallow_path(url: &str) -> String {
+    if url.ends_with('/') && url != "/" { url.to_owned() } else { format!("{}$", url) }
+}
+
+pub async fn robots(robots: web::Data<Robots>) -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain").body(robots.body().to_owned())
+}
 
-    // Add additional rules to allow or disallow specific paths
-    writeln!(buffer, "Disallow: /path/to/disallowed-page")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(
-        HttpResponse::Ok()
-            .content_type("text/plain")
-            .body(String::from_utf8(buffer.into_inner()).unwrap())
-    )
+    #[test]
+    fn disallow_path_matches_only_the_page() {
+        assert_eq!(disallow_path("/secret"), "/secret$");
+        assert_eq!(disallow_path("/de/secret"), "/de/secret$");
+        assert_eq!(disallow_path("/secret/"), "/secret/");
+        assert_eq!(disallow_path("/"), "/$");
+    }
 }
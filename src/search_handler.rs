@@ -0,0 +1,249 @@
+// src/search_handler.rs
+
+use actix_web::{ web, HttpResponse };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use crate::config::Config;
+use crate::page::{ self, Page };
+use crate::render::{ self, escape };
+use crate::summary;
+
+// Relative weight of a term depending on where it appears in the page
+const TITLE_WEIGHT: f32 = 10.0;
+const HEADING_WEIGHT: f32 = 5.0;
+const TAG_WEIGHT: f32 = 5.0;
+const BODY_WEIGHT: f32 = 1.0;
+
+// Number of words shown on each side of the first match in a snippet
+const SNIPPET_CONTEXT_WORDS: usize = 15;
+const DEFAULT_LIMIT: usize = 10;
+
+/// Searchable text of a single page. This is also the format of the
+/// `search-index.json` file written by the static build.
+#[derive(Debug, Serialize)]
+pub struct SearchDocument {
+    pub url: String,
+    pub title: String,
+    pub headings: Vec<String>,
+    pub tags: Vec<String>,
+    pub body: String,
+}
+
+/// In-process inverted index over every indexable page
+pub struct SearchIndex {
+    documents: Vec<SearchDocument>,
+    /// Term -> (document index, weighted term frequency)
+    terms: HashMap<String, Vec<(usize, f32)>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub url: String,
+    pub title: String,
+    /// HTML excerpt of the body with matching words wrapped in `<mark>`
+    pub snippet: String,
+    pub score: f32,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+impl SearchIndex {
+    pub fn new(documents: Vec<SearchDocument>) -> SearchIndex {
+        let mut terms: HashMap<String, HashMap<usize, f32>> = HashMap::new();
+        for (i, document) in documents.iter().enumerate() {
+            let fields = [
+                (vec![document.title.as_str()], TITLE_WEIGHT),
+                (document.headings.iter().map(String::as_str).collect(), HEADING_WEIGHT),
+                (document.tags.iter().map(String::as_str).collect(), TAG_WEIGHT),
+                (vec![document.body.as_str()], BODY_WEIGHT),
+            ];
+            for (texts, weight) in fields {
+                for term in texts.into_iter().flat_map(tokenize) {
+                    *terms.entry(term).or_default().entry(i).or_default() += weight;
+                }
+            }
+        }
+
+        let terms = terms
+            .into_iter()
+            .map(|(term, postings)| (term, postings.into_iter().collect()))
+            .collect();
+
+        SearchIndex { documents, terms }
+    }
+
+    pub fn documents(&self) -> &[SearchDocument] {
+        &self.documents
+    }
+
+    /// Ranks documents by the sum of their weighted term frequencies for each
+    /// query term, scaled by how rare the term is across the site
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let query_terms = tokenize(query);
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+
+        for term in &query_terms {
+            if let Some(postings) = self.terms.get(term) {
+                let idf = (1.0 + (self.documents.len() as f32) / (postings.len() as f32)).ln();
+                for (document, frequency) in postings {
+                    *scores.entry(*document).or_default() += frequency * idf;
+                }
+            }
+        }
+
+        let mut ranked: Vec<(usize, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(i, score)| {
+                let document = &self.documents[i];
+                SearchResult {
+                    url: document.url.clone(),
+                    title: document.title.clone(),
+                    snippet: snippet(&document.body, &query_terms),
+                    score,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn build_search_index(config: &Config) -> Result<SearchIndex, Box<dyn std::error::Error>> {
+    let documents = page::load_pages(config)?
+        .iter()
+        .filter(|page| !page.front_matter.noindex)
+        .map(|page| search_document(page, config))
+        .collect::<Result<_, _>>()?;
+
+    Ok(SearchIndex::new(documents))
+}
+
+/// Indexes a page as it is rendered, so included snippets, shortcodes,
+/// admonitions and math are searched by their output rather than their syntax
+fn search_document(page: &Page, config: &Config) -> Result<SearchDocument, Box<dyn std::error::Error>> {
+    let front_matter = &page.front_matter;
+    let (headings, body) = html_headings(&render::render_markdown(page, config)?);
+
+    let tags = front_matter.keywords
+        .iter()
+        .flat_map(|keywords| keywords.split(','))
+        .chain(front_matter.tags.iter().map(String::as_str))
+        .map(|tag| tag.trim().to_owned())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    Ok(SearchDocument {
+        url: page.url.clone(),
+        title: front_matter.title.clone(),
+        headings,
+        tags,
+        body: collapse_whitespace(&body),
+    })
+}
+
+/// Text of the `<h1>`-`<h6>` headings in rendered HTML, and of the rest of
+/// it. The TeX source KaTeX keeps in MathML `<annotation>`s is left out.
+fn html_headings(html: &str) -> (Vec<String>, String) {
+    let mut headings = Vec::new();
+    let mut remaining = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        let tag = &rest[start + 1..];
+        let name = &tag[..tag.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(tag.len())];
+        let close = format!("</{}>", name);
+        let end = tag
+            .find(&close)
+            .filter(|_| matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "annotation"));
+        match end {
+            Some(end) => {
+                remaining.push_str(&rest[..start]);
+                remaining.push(' ');
+                if name != "annotation" {
+                    let inner = tag[..end].split_once('>').map(|(_, inner)| inner).unwrap_or("");
+                    headings.push(collapse_whitespace(&summary::html_text(inner)));
+                }
+                rest = &tag[end + close.len()..];
+            }
+            None => {
+                remaining.push_str(&rest[..start + 1]);
+                rest = tag;
+            }
+        }
+    }
+    remaining.push_str(rest);
+
+    (headings, summary::html_text(&remaining))
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Excerpt of `body` around the first query term, HTML-escaped, with every
+/// matching word highlighted
+fn snippet(body: &str, query_terms: &[String]) -> String {
+    let words: Vec<&str> = body.split_whitespace().collect();
+    let matches = |word: &str| tokenize(word).iter().any(|term| query_terms.contains(term));
+
+    let first_match = words
+        .iter()
+        .position(|word| matches(word))
+        .unwrap_or(0);
+    let start = first_match.saturating_sub(SNIPPET_CONTEXT_WORDS);
+    let end = (first_match + SNIPPET_CONTEXT_WORDS + 1).min(words.len());
+
+    let mut snippet: Vec<String> = words[start..end]
+        .iter()
+        .map(|word| {
+            if matches(word) {
+                format!("<mark>{}</mark>", escape(word))
+            } else {
+                escape(word)
+            }
+        })
+        .collect();
+    if start > 0 {
+        snippet.insert(0, "…".to_owned());
+    }
+    if end < words.len() {
+        snippet.push("…".to_owned());
+    }
+
+    snippet.join(" ")
+}
+
+pub async fn search(query: web::Query<SearchQuery>, index: web::Data<SearchIndex>) -> HttpResponse {
+    let results = index.search(&query.q, query.limit.unwrap_or(DEFAULT_LIMIT));
+
+    HttpResponse::Ok().json(serde_json::json!({ "query": query.q, "results": results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_headings_splits_headings_from_body() {
+        let html = concat!(
+            r#"<h2 id="setup">Set <code>up</code></h2><p>Run &amp; wait</p>"#,
+            r#"<math><mi>x</mi><annotation encoding="application/x-tex">\alpha</annotation></math>"#
+        );
+        let (headings, body) = html_headings(html);
+        assert_eq!(headings, vec!["Set up"]);
+        assert_eq!(collapse_whitespace(&body), "Run & wait x");
+    }
+}
@@ -0,0 +1,162 @@
+// src/seo.rs
+
+use serde_json::{ json, Value };
+use crate::config::Config;
+use crate::page::{ self, Page };
+use crate::render::escape;
+
+/// Head tags for a page: the canonical link, a robots `noindex` meta tag for
+/// `noindex` pages, Open Graph and Twitter Card meta tags, and schema.org
+/// JSON-LD. Templates include them with `{{seo}}`.
+pub fn seo_tags(page: &Page, config: &Config) -> String {
+    let front_matter = &page.front_matter;
+    let canonical = config.absolute_url(&page.url);
+    let is_article = !page.key.is_empty();
+    let image = front_matter.image.as_deref().or(config.image.as_deref()).map(|image| {
+        if image.starts_with("http://") || image.starts_with("https://") {
+            image.to_owned()
+        } else {
+            config.absolute_url(image)
+        }
+    });
+
+    let mut tags = vec![format!(r#"<link rel="canonical" href="{}" />"#, escape(&canonical))];
+    if front_matter.noindex {
+        tags.push(r#"<meta name="robots" content="noindex" />"#.to_owned());
+    }
+
+    // Alternate links between translations of the page
+    let translations = page::translations(page, config);
+    if translations.len() > 1 {
+        for (lang, url) in &translations {
+            tags.push(
+                format!(
+                    r#"<link rel="alternate" hreflang="{}" href="{}" />"#,
+                    escape(lang),
+                    escape(&config.absolute_url(url))
+                )
+            );
+        }
+        if let Some((_, url)) = translations.iter().find(|(lang, _)| *lang == config.default_language) {
+            tags.push(
+                format!(
+                    r#"<link rel="alternate" hreflang="x-default" href="{}" />"#,
+                    escape(&config.absolute_url(url))
+                )
+            );
+        }
+    }
+
+    let mut property = |name: &str, content: &str| {
+        tags.push(format!(r#"<meta property="{}" content="{}" />"#, name, escape(content)));
+    };
+    property("og:site_name", config.site_title(&page.lang));
+    property("og:locale", &page.lang);
+    property("og:type", if is_article { "article" } else { "website" });
+    property("og:title", &front_matter.title);
+    property("og:url", &canonical);
+    if let Some(description) = &front_matter.description {
+        property("og:description", description);
+    }
+    if let Some(image) = &image {
+        property("og:image", image);
+    }
+    if let (true, Some(date)) = (is_article, &front_matter.date) {
+        property("article:published_time", date);
+    }
+
+    let mut name = |name: &str, content: &str| {
+        tags.push(format!(r#"<meta name="{}" content="{}" />"#, name, escape(content)));
+    };
+    name("twitter:card", if image.is_some() { "summary_large_image" } else { "summary" });
+    if let Some(twitter) = &config.twitter {
+        name("twitter:site", twitter);
+    }
+    name("twitter:title", &front_matter.title);
+    if let Some(description) = &front_matter.description {
+        name("twitter:description", description);
+    }
+    if let Some(image) = &image {
+        name("twitter:image", image);
+    }
+
+    let json_ld = Value::Array(json_ld(page, config, &canonical, image.as_deref()));
+    // Keep "</script>" inside string values from closing the script element
+    let json_ld = json_ld.to_string().replace("</", "<\\/");
+    tags.push(format!(r#"<script type="application/ld+json">{}</script>"#, json_ld));
+
+    tags.join("\n    ")
+}
+
+fn json_ld(page: &Page, config: &Config, canonical: &str, image: Option<&str>) -> Vec<Value> {
+    let front_matter = &page.front_matter;
+    let home = config.absolute_url(page.home_url());
+
+    if page.key.is_empty() {
+        return vec![
+            json!({
+                "@context": "https://schema.org",
+                "@type": "WebSite",
+                "name": config.site_title(&page.lang),
+                "url": home,
+                "description": front_matter.description.as_deref().or(config.description.as_deref()),
+            })
+        ];
+    }
+
+    let mut article =
+        json!({
+            "@context": "https://schema.org",
+            "@type": "Article",
+            "headline": front_matter.title,
+            "url": canonical,
+            "mainEntityOfPage": canonical,
+        });
+    if let Some(description) = &front_matter.description {
+        article["description"] = json!(description);
+    }
+    if let Some(image) = image {
+        article["image"] = json!(image);
+    }
+    if let Some(date) = &front_matter.date {
+        article["datePublished"] = json!(date);
+    }
+    if let Some(lastmod) = &front_matter.lastmod {
+        article["dateModified"] = json!(lastmod);
+    }
+    if let Some(author) = &front_matter.author {
+        article["author"] = json!({ "@type": "Person", "name": author });
+    }
+
+    // One breadcrumb per route segment, starting at the language's home page
+    let mut crumbs = vec![(config.site_title(&page.lang).to_owned(), home)];
+    let segments: Vec<&str> = page.key.split('/').collect();
+    for (i, segment) in segments.iter().enumerate() {
+        let path = page::canonical_url(
+            &format!("{}/{}", page.home_url().trim_end_matches('/'), segments[..=i].join("/")),
+            config
+        );
+        let name = if i + 1 == segments.len() {
+            front_matter.title.clone()
+        } else {
+            segment.to_string()
+        };
+        crumbs.push((name, config.absolute_url(&path)));
+    }
+    let breadcrumbs: Vec<Value> = crumbs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, item))| {
+            json!({ "@type": "ListItem", "position": i + 1, "name": name, "item": item })
+        })
+        .collect();
+
+    vec![
+        article,
+        json!({
+            "@context": "https://schema.org",
+            "@type": "BreadcrumbList",
+            "itemListElement": breadcrumbs,
+        })
+    ]
+}
@@ -0,0 +1,369 @@
+// src/shortcodes.rs
+
+use pulldown_cmark::{ Event, Parser, Tag };
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{ Path, PathBuf };
+use crate::config::Config;
+use crate::data;
+use crate::includes::SourceMap;
+use crate::page::Page;
+use crate::render::{ self, escape };
+
+pub const SHORTCODES_DIR: &str = "templates/shortcodes";
+
+// Shortcodes are swapped for `PLACEHOLDER_START<index>PLACEHOLDER_END` before
+// the markdown is parsed, and their HTML is put back afterwards. Private use
+// characters are left alone by the markdown parser.
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+/// A shortcode found in markdown
+struct Shortcode<'a> {
+    name: &'a str,
+    args: HashMap<&'a str, String>,
+    /// Markdown between `{% name %}` and `{% end %}`, with where it was written
+    body: Option<(&'a str, SourceMap)>,
+    /// `file:line` of the shortcode, for error messages
+    position: String,
+    /// Byte range of the whole shortcode in the markdown
+    start: usize,
+    end: usize,
+}
+
+/// Replaces the shortcodes in a piece of a page's markdown, which `sources`
+/// maps to where it was written, with placeholders. Returns the markdown and
+/// the HTML of each shortcode, for `insert`.
+///
+/// Inline shortcodes are written `{{< youtube id="dQw4w9WgXcQ" >}}` and block
+/// shortcodes `{% note type="warning" %}Markdown body{% end %}`. Each renders
+/// `templates/shortcodes/<name>.html`, where `{{arg}}` is replaced with the
+/// (escaped) argument and `{{body}}` with the rendered body. Shortcode
+/// templates can use the data files like page templates (see `data::render`).
+/// Shortcodes in code blocks and code spans are left as they are.
+pub fn expand(
+    markdown: &str,
+    sources: &SourceMap,
+    page: &Page,
+    config: &Config
+) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+    let mut output = String::new();
+    let mut html = Vec::new();
+    let mut rest = 0;
+
+    let code = code_ranges(markdown);
+    let mut search = 0;
+    while let Some(shortcode) = next_shortcode(markdown, search, &code, sources)? {
+        output.push_str(&markdown[rest..shortcode.start]);
+        output.push(PLACEHOLDER_START);
+        output.push_str(&html.len().to_string());
+        output.push(PLACEHOLDER_END);
+        html.push(render_shortcode(&shortcode, page, config)?);
+
+        rest = shortcode.end;
+        search = shortcode.end;
+    }
+    output.push_str(&markdown[rest..]);
+
+    Ok((output, html))
+}
+
+/// Puts the HTML of each shortcode back in place of its placeholder. A
+/// shortcode on its own line replaces the paragraph markdown wrapped it in.
+pub fn insert(html_output: &str, shortcodes: &[String]) -> String {
+    let mut html_output = html_output.to_owned();
+    for (i, html) in shortcodes.iter().enumerate() {
+        let placeholder = format!("{}{}{}", PLACEHOLDER_START, i, PLACEHOLDER_END);
+        html_output = html_output
+            .replace(&format!("<p>{}</p>", placeholder), html)
+            .replace(&placeholder, html);
+    }
+
+    html_output
+}
+
+fn render_shortcode(
+    shortcode: &Shortcode,
+    page: &Page,
+    config: &Config
+) -> Result<String, Box<dyn std::error::Error>> {
+    let template_path = template_path(shortcode.name);
+    let template = match template_path.as_ref().filter(|path| path.is_file()) {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            return Err(
+                format!(
+                    "{}: unknown shortcode `{}` (no {}/{}.html)",
+                    shortcode.position,
+                    shortcode.name,
+                    SHORTCODES_DIR,
+                    shortcode.name
+                ).into()
+            );
+        }
+    };
+
+    let body = match &shortcode.body {
+        Some((body, sources)) => render::render_fragment(body, sources, page, config)?,
+        None => String::new(),
+    };
+
+    let template = data::render(&template, &template_path.unwrap_or_default().to_string_lossy())?;
+    let html = render::replace_helper(&template, "{{", "}}", |key| {
+        match key.trim() {
+            "body" => body.clone(),
+            key => escape(shortcode.args.get(key).map(String::as_str).unwrap_or("")),
+        }
+    });
+
+    Ok(html.trim().to_owned())
+}
+
+/// Template file for a shortcode name, if the name is valid
+fn template_path(name: &str) -> Option<PathBuf> {
+    let is_valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    is_valid.then(|| Path::new(SHORTCODES_DIR).join(format!("{}.html", name)))
+}
+
+/// Finds the next shortcode at or after `from` that isn't in `code`
+fn next_shortcode<'a>(
+    markdown: &'a str,
+    from: usize,
+    code: &[Range<usize>],
+    sources: &SourceMap
+) -> Result<Option<Shortcode<'a>>, Box<dyn std::error::Error>> {
+    let in_code = |offset: usize| code.iter().any(|range| range.contains(&offset));
+    let find = |pattern: &str| {
+        let mut search = from;
+        while let Some(i) = markdown[search..].find(pattern) {
+            if !in_code(search + i) {
+                return Some(search + i);
+            }
+            search += i + pattern.len();
+        }
+        None
+    };
+    let inline = find("{{<");
+    let block = find("{%");
+    let error = |offset: usize, message: String| -> Box<dyn std::error::Error> {
+        format!("{}: {}", sources.position(markdown, offset), message).into()
+    };
+
+    let start = match (inline, block) {
+        (Some(inline), Some(block)) => inline.min(block),
+        (Some(start), None) | (None, Some(start)) => start,
+        (None, None) => {
+            return Ok(None);
+        }
+    };
+
+    let (open, close) = if inline == Some(start) { ("{{<", ">}}") } else { ("{%", "%}") };
+    let tag_end = match markdown[start..].find(close) {
+        Some(i) => start + i + close.len(),
+        None => {
+            return Err(error(start, format!("unclosed shortcode, expected `{}`", close)));
+        }
+    };
+    let tag = markdown[start + open.len()..tag_end - close.len()].trim();
+    let (name, args) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let args = parse_args(args).map_err(|e| error(start, format!("shortcode `{}`: {}", name, e)))?;
+
+    if open == "{{<" {
+        return Ok(
+            Some(Shortcode {
+                name,
+                args,
+                body: None,
+                position: sources.position(markdown, start),
+                start,
+                end: tag_end,
+            })
+        );
+    }
+    if name == "end" {
+        return Err(error(start, "`{% end %}` without an opening shortcode".to_owned()));
+    }
+
+    // Find the matching `{% end %}`, counting nested block shortcodes
+    let mut depth = 1;
+    let mut search = tag_end;
+    while let Some(i) = markdown[search..].find("{%") {
+        let nested_start = search + i;
+        if in_code(nested_start) {
+            search = nested_start + 2;
+            continue;
+        }
+        let nested_end = match markdown[nested_start..].find("%}") {
+            Some(i) => nested_start + i + 2,
+            None => {
+                return Err(error(nested_start, "unclosed shortcode, expected `%}`".to_owned()));
+            }
+        };
+        if markdown[nested_start + 2..nested_end - 2].trim() == "end" {
+            depth -= 1;
+        } else {
+            depth += 1;
+        }
+        if depth == 0 {
+            let body = tag_end..nested_start;
+            return Ok(
+                Some(Shortcode {
+                    name,
+                    args,
+                    body: Some((&markdown[body.clone()], sources.slice(markdown, body))),
+                    position: sources.position(markdown, start),
+                    start,
+                    end: nested_end,
+                })
+            );
+        }
+        search = nested_end;
+    }
+
+    Err(error(start, format!("shortcode `{}` has no `{{% end %}}`", name)))
+}
+
+/// Parses `key="value" other='value' bare=value` arguments
+pub fn parse_args(args: &str) -> Result<HashMap<&str, String>, String> {
+    let mut parsed = HashMap::new();
+    let mut rest = args.trim_start();
+
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("expected `key=\"value\"`, found `{}`", rest))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("invalid argument name `{}`", key));
+        }
+
+        let value = value.trim_start();
+        let (value, remainder) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = value[1..].find(quote).ok_or_else(|| format!("unclosed quote in `{}`", key))?;
+                (&value[1..end + 1], &value[end + 2..])
+            }
+            _ => value.split_at(value.find(char::is_whitespace).unwrap_or(value.len())),
+        };
+
+        parsed.insert(key, value.to_owned());
+        rest = remainder.trim_start();
+    }
+
+    Ok(parsed)
+}
+
+/// Byte ranges of the code in markdown, where shortcodes and includes are
+/// left as they are: fenced and indented code blocks, and code spans
+pub fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new(markdown)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Byte ranges of the ```` ``` ```` and `~~~` fenced code blocks in markdown
+pub fn fenced_code_blocks(markdown: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(usize, &str)> = None;
+    let mut offset = 0;
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match open {
+            Some((start, fence)) if trimmed.starts_with(fence) => {
+                blocks.push(start..offset + line.len());
+                open = None;
+            }
+            None => {
+                if let Some(fence) = opening_fence(trimmed) {
+                    open = Some((offset, fence));
+                }
+            }
+            _ => {}
+        }
+        offset += line.len();
+    }
+    if let Some((start, _)) = open {
+        blocks.push(start..markdown.len());
+    }
+
+    blocks
+}
+
+/// The ```` ``` ```` or `~~~` run (three or more) opening a fenced code block
+/// on a trimmed line
+pub fn opening_fence(line: &str) -> Option<&str> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = line.len() - line.trim_start_matches(marker).len();
+    (run >= 3).then(|| &line[..run])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_accepts_quoted_and_bare_values() {
+        let args = parse_args(r#" id="a b" type='x "y"' width=300 "#).unwrap();
+        assert_eq!(args.len(), 3);
+        assert_eq!(args["id"], "a b");
+        assert_eq!(args["type"], r#"x "y""#);
+        assert_eq!(args["width"], "300");
+        assert!(parse_args("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_args_rejects_malformed_arguments() {
+        assert!(parse_args("id").is_err());
+        assert!(parse_args(r#"id="open"#).is_err());
+        assert!(parse_args(r#"=value"#).is_err());
+    }
+
+    fn shortcode_names(markdown: &str) -> Result<Vec<&str>, Box<dyn std::error::Error>> {
+        let sources = SourceMap::new(Path::new("page.md"), 1);
+        let code = code_ranges(markdown);
+        let mut names = Vec::new();
+        let mut search = 0;
+        while let Some(shortcode) = next_shortcode(markdown, search, &code, &sources)? {
+            names.push(shortcode.name);
+            search = shortcode.end;
+        }
+        Ok(names)
+    }
+
+    #[test]
+    fn shortcodes_in_code_spans_are_left_alone() {
+        let markdown = "Inline `{{< youtube >}}` and ``{% note %}`` examples.\n\n{{< video >}}\n";
+        assert_eq!(shortcode_names(markdown).unwrap(), vec!["video"]);
+    }
+
+    #[test]
+    fn shortcodes_in_indented_code_blocks_are_left_alone() {
+        let markdown = "Example:\n\n    {% note %}\n    {{< youtube >}}\n\n{% tip %}Use `{% end %}`.{% end %}\n";
+        assert_eq!(shortcode_names(markdown).unwrap(), vec!["tip"]);
+    }
+
+    #[test]
+    fn block_shortcodes_nest_and_report_missing_ends() {
+        let markdown = "{% note %}a {% tip %}b{% end %} c{% end %}";
+        assert_eq!(shortcode_names(markdown).unwrap(), vec!["note"]);
+
+        let error = shortcode_names("text\n{% note %}body").unwrap_err().to_string();
+        assert_eq!(error, "page.md:2: shortcode `note` has no `{% end %}`");
+        let error = shortcode_names("{% end %}").unwrap_err().to_string();
+        assert_eq!(error, "page.md:1: `{% end %}` without an opening shortcode");
+        let error = shortcode_names("{{< youtube id=\"x >}}").unwrap_err().to_string();
+        assert_eq!(error, "page.md:1: shortcode `youtube`: unclosed quote in `id`");
+    }
+
+    #[test]
+    fn fenced_code_blocks_cover_fences_of_either_kind() {
+        let markdown = "a\n```rust\n{{< x >}}\n```\nb\n~~~~\nc\n";
+        assert_eq!(fenced_code_blocks(markdown), vec![2..24, 26..33]);
+    }
+}
@@ -1,61 +1,289 @@
 // src/sitemap_handler.rs
 
-use actix_web::{ HttpResponse };
-use sitemap::structs::{ ChangeFreq, UrlEntry };
+use actix_web::{ web, HttpResponse };
+use chrono::{ DateTime, FixedOffset, NaiveDate, Utc };
+use sitemap::structs::{ ChangeFreq, SiteMapEntry, UrlEntry };
 use sitemap::writer::SiteMapWriter;
-use url::Url;
+use std::cell::OnceCell;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Cursor;
+use std::path::PathBuf;
+use std::process::Command;
+use crate::config::Config;
+use crate::page::{ self, Page };
 
-pub async fn sitemap() -> Result<HttpResponse, actix_web::Error> {
-    let mut buffer = Cursor::new(Vec::<u8>::new());
+// The sitemaps.org protocol allows at most 50,000 URLs per sitemap file
+const MAX_URLS_PER_SITEMAP: usize = 50_000;
+
+/// Date of the last commit touching each file
+type GitDates = HashMap<PathBuf, DateTime<FixedOffset>>;
+
+/// Rendered sitemap files, keyed by file name (`sitemap.xml`, `sitemap-1.xml`, ...)
+pub struct Sitemap {
+    files: Vec<(String, String)>,
+}
+
+impl Sitemap {
+    pub fn get(&self, file_name: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|(name, _)| name == file_name)
+            .map(|(_, xml)| xml.as_str())
+    }
+
+    pub fn files(&self) -> &[(String, String)] {
+        &self.files
+    }
+}
+
+/// Builds the sitemap for every page in the routes directory.
+///
+/// Multilingual sites get one sitemap per language (`sitemap-en.xml`, ...), and
+/// any sitemap with more than `MAX_URLS_PER_SITEMAP` URLs is split into numbered
+/// parts. In both cases `sitemap.xml` becomes a sitemap index pointing at them.
+pub fn build_sitemap(config: &Config) -> Result<Sitemap, Box<dyn std::error::Error>> {
+    let pages = page::load_pages(config)?;
+    // Commit dates of the site's files, read from git the first time a page
+    // needs one
+    let git_dates = OnceCell::new();
+
+    // URL entries grouped by sitemap file name prefix
+    let mut groups: Vec<(String, Vec<UrlEntry>)> = Vec::new();
+    for page in &pages {
+        let options = page.front_matter.sitemap.as_ref();
+        if page.front_matter.noindex || options.is_some_and(|options| options.exclude) {
+            continue;
+        }
+
+        let changefreq = match options.and_then(|options| options.changefreq.clone()) {
+            Some(changefreq) => {
+                parse_changefreq(changefreq).map_err(|e| format!("{}: {}", page.path.display(), e))?
+            }
+            None => ChangeFreq::Weekly,
+        };
+
+        let mut entry = UrlEntry::builder()
+            .loc(config.absolute_url(&page.url))
+            .changefreq(changefreq);
+        if let Some(lastmod) = lastmod(page, &git_dates) {
+            entry = entry.lastmod(lastmod);
+        }
+        if let Some(priority) = options.and_then(|options| options.priority) {
+            entry = entry.priority(priority);
+        }
 
-    let sitemap_writer = SiteMapWriter::new(&mut buffer);
+        let prefix = if config.languages.len() > 1 {
+            format!("sitemap-{}", page.lang)
+        } else {
+            "sitemap".to_owned()
+        };
+        match groups.iter_mut().find(|(group, _)| *group == prefix) {
+            Some((_, entries)) => entries.push(entry.build()?),
+            None => groups.push((prefix, vec![entry.build()?])),
+        }
+    }
 
-    let entries = fs::read_dir("./routes").map_err(|e| {
-        eprintln!("Failed to read directory: {:?}", e);
-        actix_web::Error::from(e)
-    })?;
+    Ok(Sitemap { files: sitemap_files(groups, MAX_URLS_PER_SITEMAP, config)? })
+}
 
-    let mut urlwriter = sitemap_writer.start_urlset().map_err(|e| {
-        eprintln!("Failed to start urlset: {:?}", e);
-        actix_web::Error::from(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-    })?;
+/// Sitemap files for URL entries grouped by file name prefix: one
+/// `sitemap.xml`, or a sitemap index pointing at a file per group, split into
+/// numbered parts of at most `max_urls` URLs
+fn sitemap_files(
+    mut groups: Vec<(String, Vec<UrlEntry>)>,
+    max_urls: usize,
+    config: &Config
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    if groups.len() <= 1 && groups.iter().all(|(_, entries)| entries.len() <= max_urls) {
+        let entries = groups.pop().map(|(_, entries)| entries).unwrap_or_default();
+        files.push(("sitemap.xml".to_owned(), write_urlset(entries)?));
+    } else {
+        let mut buffer = Cursor::new(Vec::<u8>::new());
+        let mut index_writer = SiteMapWriter::new(&mut buffer).start_sitemapindex()?;
+
+        for (prefix, mut entries) in groups {
+            let mut chunks = Vec::new();
+            while !entries.is_empty() {
+                let rest = entries.split_off(entries.len().min(max_urls));
+                chunks.push(entries);
+                entries = rest;
+            }
 
+            let numbered = chunks.len() > 1 || prefix == "sitemap";
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let file_name = if numbered {
+                    format!("{}-{}.xml", prefix, i + 1)
+                } else {
+                    format!("{}.xml", prefix)
+                };
+                index_writer.sitemap(
+                    SiteMapEntry::builder().loc(config.absolute_url(&file_name)).build()?
+                )?;
+                files.push((file_name, write_urlset(chunk)?));
+            }
+        }
+
+        index_writer.end()?;
+        files.insert(0, ("sitemap.xml".to_owned(), String::from_utf8(buffer.into_inner())?));
+    }
+
+    Ok(files)
+}
+
+fn write_urlset(entries: Vec<UrlEntry>) -> Result<String, Box<dyn std::error::Error>> {
+    let mut buffer = Cursor::new(Vec::<u8>::new());
+    let mut urlwriter = SiteMapWriter::new(&mut buffer).start_urlset()?;
     for entry in entries {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if path.is_file() && path.extension().unwrap_or_default() == "md" {
-                let path_str = path.to_str().unwrap().replace("./routes", "").replace(".md", "");
-                let url = Url::parse(&format!("http://127.0.0.1:8080{}", path_str)).unwrap();
-                let entry = UrlEntry::builder()
-                    .loc(url.as_str())
-                    .changefreq(ChangeFreq::Weekly)
-                    .build()
-                    .map_err(|e| {
-                        eprintln!("Failed to create UrlEntry: {:?}", e);
-                        actix_web::Error::from(
-                            std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
-                        )
-                    })?;
-                urlwriter.url(entry).map_err(|e| {
-                    eprintln!("Failed to write URL entry: {:?}", e);
-                    actix_web::Error::from(
-                        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
-                    )
-                })?;
+        urlwriter.url(entry)?;
+    }
+    urlwriter.end()?;
+
+    Ok(String::from_utf8(buffer.into_inner())?)
+}
+
+/// `sitemap.changefreq` from a page's front matter
+fn parse_changefreq(changefreq: String) -> Result<ChangeFreq, String> {
+    match ChangeFreq::from(changefreq.clone()) {
+        ChangeFreq::ParseErr(_) => {
+            Err(
+                format!(
+                    "invalid sitemap.changefreq \"{}\", expected always, hourly, daily, weekly, monthly, yearly or never",
+                    changefreq
+                )
+            )
+        }
+        changefreq => Ok(changefreq),
+    }
+}
+
+/// Last modification time of a page: `lastmod` or `date` from the front matter,
+/// then the last git commit touching the file, then the file's mtime
+fn lastmod(page: &Page, git_dates: &OnceCell<GitDates>) -> Option<DateTime<FixedOffset>> {
+    let front_matter = &page.front_matter;
+    front_matter.lastmod
+        .as_deref()
+        .or(front_matter.date.as_deref())
+        .and_then(parse_date)
+        .or_else(|| git_dates.get_or_init(git_dates_by_file).get(&page.path).copied())
+        .or_else(|| {
+            let modified = fs::metadata(&page.path).ok()?.modified().ok()?;
+            Some(DateTime::<Utc>::from(modified).fixed_offset())
+        })
+}
+
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+            Some(date.and_hms_opt(0, 0, 0)?.and_utc().fixed_offset())
+        })
+}
+
+/// Date of the last commit touching each file under the current directory,
+/// from a single `git log`, or nothing outside a git repository
+fn git_dates_by_file() -> GitDates {
+    let output = Command::new("git")
+        .args(["-c", "core.quotePath=false", "log", "--name-only", "--relative", "--format=%x00%cI"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => parse_git_log(&String::from_utf8_lossy(&output.stdout)),
+        _ => HashMap::new(),
+    }
+}
+
+/// Reads `git log --name-only --format=%x00%cI` output, newest commit first,
+/// keeping the first date seen for each file
+fn parse_git_log(log: &str) -> GitDates {
+    let mut dates = HashMap::new();
+    let mut date = None;
+    for line in log.lines() {
+        match line.strip_prefix('\0') {
+            Some(commit_date) => date = DateTime::parse_from_rfc3339(commit_date).ok(),
+            None if !line.is_empty() => {
+                if let Some(date) = date {
+                    dates.entry(PathBuf::from(line)).or_insert(date);
+                }
             }
+            None => {}
         }
     }
 
-    urlwriter.end().map_err(|e| {
-        eprintln!("Failed to end urlset: {:?}", e);
-        actix_web::Error::from(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-    })?;
+    dates
+}
+
+pub async fn sitemap(sitemap: web::Data<Sitemap>) -> HttpResponse {
+    sitemap_file(&sitemap, "sitemap.xml")
+}
+
+pub async fn sitemap_part(path: web::Path<String>, sitemap: web::Data<Sitemap>) -> HttpResponse {
+    sitemap_file(&sitemap, &format!("sitemap-{}.xml", path.into_inner()))
+}
+
+fn sitemap_file(sitemap: &Sitemap, file_name: &str) -> HttpResponse {
+    match sitemap.get(file_name) {
+        Some(xml) => HttpResponse::Ok().content_type("application/xml").body(xml.to_owned()),
+        None => HttpResponse::NotFound().body(format!("Sitemap not found: {}", file_name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(
-        HttpResponse::Ok()
-            .content_type("application/xml")
-            .body(String::from_utf8(buffer.into_inner()).unwrap())
-    )
+    use std::path::Path;
+
+    #[test]
+    fn parse_git_log_keeps_the_newest_date_of_each_file() {
+        let log = concat!(
+            "\u{0}2024-03-02T10:00:00+01:00\n\nroutes/a.md\n\n",
+            "\u{0}2024-01-05T09:30:00Z\n\nroutes/a.md\nroutes/b.md\n"
+        );
+        let dates = parse_git_log(log);
+        assert_eq!(dates.len(), 2);
+        assert_eq!(dates[Path::new("routes/a.md")].to_rfc3339(), "2024-03-02T10:00:00+01:00");
+        assert_eq!(dates[Path::new("routes/b.md")].to_rfc3339(), "2024-01-05T09:30:00+00:00");
+    }
+
+    fn entries(count: usize) -> Vec<UrlEntry> {
+        (0..count)
+            .map(|i| UrlEntry::builder().loc(format!("https://example.com/{}", i)).build().unwrap())
+            .collect()
+    }
+
+    fn file_names(files: &[(String, String)]) -> Vec<&str> {
+        files.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn small_sitemaps_are_a_single_file() {
+        let files = sitemap_files(vec![("sitemap".to_owned(), entries(3))], 3, &Config::default()).unwrap();
+        assert_eq!(file_names(&files), ["sitemap.xml"]);
+        assert!(files[0].1.contains("<urlset"));
+        assert_eq!(files[0].1.matches("<url>").count(), 3);
+    }
+
+    #[test]
+    fn large_sitemaps_are_split_behind_an_index() {
+        let files = sitemap_files(vec![("sitemap".to_owned(), entries(5))], 2, &Config::default()).unwrap();
+        assert_eq!(file_names(&files), ["sitemap.xml", "sitemap-1.xml", "sitemap-2.xml", "sitemap-3.xml"]);
+        assert!(files[0].1.contains("<sitemapindex"));
+        assert!(files[0].1.contains("<loc>http://127.0.0.1:8080/sitemap-3.xml</loc>"));
+        assert_eq!(files[3].1.matches("<url>").count(), 1);
+    }
+
+    #[test]
+    fn languages_get_a_sitemap_each() {
+        let groups = vec![("sitemap-en".to_owned(), entries(3)), ("sitemap-de".to_owned(), entries(1))];
+        let files = sitemap_files(groups, 2, &Config::default()).unwrap();
+        assert_eq!(file_names(&files), ["sitemap.xml", "sitemap-en-1.xml", "sitemap-en-2.xml", "sitemap-de.xml"]);
+    }
+
+    #[test]
+    fn parse_changefreq_rejects_unknown_values() {
+        assert_eq!(parse_changefreq("Daily".to_owned()), Ok(ChangeFreq::Daily));
+        assert!(parse_changefreq("fortnightly".to_owned()).unwrap_err().contains("\"fortnightly\""));
+    }
 }
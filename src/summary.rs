@@ -0,0 +1,150 @@
+// src/summary.rs
+
+use pulldown_cmark::{ Event, Options, Parser, Tag };
+use crate::config::Config;
+use crate::includes;
+use crate::page::Page;
+use crate::render::{ self, escape };
+use crate::shortcodes;
+
+// A line ending the summary of a page
+const MORE_MARKER: &str = "<!-- more -->";
+
+/// Number of words in the text of a page's markdown, leaving out code blocks
+pub fn word_count(markdown: &str) -> usize {
+    text(markdown, true).split_whitespace().count()
+}
+
+/// Minutes it takes to read `words` words, rounded up and at least one
+pub fn reading_time(words: usize, words_per_minute: usize) -> usize {
+    words.div_ceil(words_per_minute.max(1)).max(1)
+}
+
+/// HTML summary of a page for listings: its rendered markdown before a
+/// `<!-- more -->` line, or else a paragraph with the first `summary.words`
+/// words of its text (leaving out headings and code blocks, for markdown)
+pub fn summary(page: &Page, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+    let text = match &page.html {
+        Some(html) => html_text(html),
+        None => {
+            let (markdown, sources) = includes::expand_with_sources(page)?;
+            if let Some(end) = more_marker(&markdown) {
+                return render::render_fragment(&markdown[..end], &sources, page, config);
+            }
+            text(&markdown, false)
+        }
+    };
+
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut summary = words[..words.len().min(config.summary.words)].join(" ");
+    if words.len() > config.summary.words {
+        summary.push('…');
+    }
+
+    Ok(format!("<p>{}</p>", escape(&summary)))
+}
+
+/// Offset of the `<!-- more -->` line in markdown, outside code blocks
+fn more_marker(markdown: &str) -> Option<usize> {
+    let code_blocks = shortcodes::fenced_code_blocks(markdown);
+    let mut offset = 0;
+
+    for line in markdown.split_inclusive('\n') {
+        if line.trim() == MORE_MARKER && !code_blocks.iter().any(|block| block.contains(&offset)) {
+            return Some(offset);
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// Plain text of markdown without code blocks or shortcode tags, and
+/// optionally without headings
+fn text(markdown: &str, headings: bool) -> String {
+    let mut text = String::new();
+    let mut skipping = false;
+
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => skipping = true,
+            Event::Start(Tag::Heading(_)) => skipping = !headings,
+            Event::End(Tag::CodeBlock(_) | Tag::Heading(_)) => {
+                skipping = false;
+                text.push(' ');
+            }
+            Event::End(Tag::Paragraph | Tag::Item | Tag::TableCell) |
+            Event::SoftBreak |
+            Event::HardBreak => text.push(' '),
+            Event::Text(t) | Event::Code(t) if !skipping => text.push_str(&t),
+            _ => {}
+        }
+    }
+
+    let text = render::replace_helper(&text, "{{<", ">}}", |_| String::new());
+    render::replace_helper(&text, "{%", "%}", |_| String::new())
+}
+
+/// Text of HTML without its tags, with the characters `escape` escapes
+/// decoded
+pub fn html_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    render::unescape(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn more_marker_splits_outside_code_blocks() {
+        let markdown = "Intro\n\n```html\n<!-- more -->\n```\n\nStill summary\n  <!-- more -->  \nRest\n";
+        let end = more_marker(markdown).unwrap();
+        assert!(markdown[..end].ends_with("Still summary\n"));
+        assert_eq!(more_marker("No marker <!-- more --> inline\n"), None);
+    }
+
+    #[test]
+    fn text_leaves_out_code_shortcodes_and_optionally_headings() {
+        let markdown = concat!(
+            "# Title\n\nSome *text* {{< youtube id=\"x\" >}} here.\n\n",
+            "```\nlet code = 1;\n```\n\n{% note %}Body{% end %}\n"
+        );
+        let words: Vec<String> = text(markdown, true).split_whitespace().map(str::to_owned).collect();
+        assert_eq!(words, ["Title", "Some", "text", "here.", "Body"]);
+        assert_eq!(text(markdown, false).split_whitespace().next(), Some("Some"));
+        assert_eq!(word_count(markdown), 5);
+    }
+
+    #[test]
+    fn reading_time_rounds_up_to_at_least_a_minute() {
+        assert_eq!(reading_time(0, 200), 1);
+        assert_eq!(reading_time(200, 200), 1);
+        assert_eq!(reading_time(201, 200), 2);
+        assert_eq!(reading_time(10, 0), 10);
+    }
+
+    #[test]
+    fn html_text_drops_tags_and_decodes_entities() {
+        let text = html_text("<p>Fish &amp; <em>chips</em></p>");
+        assert_eq!(text.split_whitespace().collect::<Vec<_>>(), ["Fish", "&", "chips"]);
+    }
+}